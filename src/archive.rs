@@ -0,0 +1,345 @@
+use crate::download::Validators;
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "manifest.toml";
+
+/// Сколько снимков зоны хранить: последние `daily` дней, по одному снимку за последние `weekly`
+/// недель и за последние `monthly` месяцев.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Retention {
+    #[serde(default = "Retention::default_daily")]
+    pub daily: usize,
+    #[serde(default = "Retention::default_weekly")]
+    pub weekly: usize,
+    #[serde(default = "Retention::default_monthly")]
+    pub monthly: usize,
+}
+
+impl Retention {
+    fn default_daily() -> usize {
+        7
+    }
+
+    fn default_weekly() -> usize {
+        4
+    }
+
+    fn default_monthly() -> usize {
+        12
+    }
+
+    /// Возвращает даты снимков, которые нужно сохранить. Самый свежий снимок сохраняется всегда.
+    pub fn retained(&self, dates: &[NaiveDate]) -> BTreeSet<NaiveDate> {
+        let mut dates = dates.to_vec();
+        dates.sort_unstable_by(|a, b| b.cmp(a));
+        dates.dedup();
+
+        let mut retained = dates
+            .iter()
+            .take(self.daily.max(1))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        retained.extend(newest_by(&dates, self.weekly, |x| {
+            (x.iso_week().year(), x.iso_week().week())
+        }));
+        retained.extend(newest_by(&dates, self.monthly, |x| (x.year(), x.month())));
+
+        retained
+    }
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            daily: Retention::default_daily(),
+            weekly: Retention::default_weekly(),
+            monthly: Retention::default_monthly(),
+        }
+    }
+}
+
+/// Самая свежая дата в каждом из первых `count` периодов. Даты отсортированы по убыванию.
+fn newest_by<K, F>(dates: &[NaiveDate], count: usize, period: F) -> Vec<NaiveDate>
+where
+    K: PartialEq,
+    F: Fn(&NaiveDate) -> K,
+{
+    let mut newest: Vec<NaiveDate> = vec![];
+
+    for date in dates {
+        if newest.last().map_or(true, |x| period(x) != period(date)) {
+            if newest.len() == count {
+                break;
+            }
+            newest.push(*date);
+        }
+    }
+
+    newest
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub date: NaiveDate,
+    pub file: String,
+    pub size: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Snapshot {
+    pub fn new(date: NaiveDate, size: u64, validators: Validators) -> Self {
+        Snapshot {
+            date,
+            file: Archive::file_name(date),
+            size,
+            etag: validators.etag,
+            last_modified: validators.last_modified,
+        }
+    }
+
+    pub fn validators(&self) -> Validators {
+        Validators {
+            etag: self.etag.clone(),
+            last_modified: self.last_modified.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Manifest {
+    latest: Option<NaiveDate>,
+    #[serde(default, rename = "snapshot")]
+    snapshots: Vec<Snapshot>,
+}
+
+/// Архив снимков зоны: файлы `<dir>/<дата>.gz` и описывающий их `<dir>/manifest.toml`
+/// с указателем на последний снимок.
+#[derive(Debug)]
+pub struct Archive {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl Archive {
+    pub fn open<P>(dir: P) -> io::Result<Archive>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let manifest = match fs::read_to_string(dir.join(MANIFEST)) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|x| io::Error::new(io::ErrorKind::InvalidData, x))?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Manifest::default(),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Archive { dir, manifest })
+    }
+
+    fn file_name(date: NaiveDate) -> String {
+        format!("{}.gz", date.format("%Y-%m-%d"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Путь к файлу снимка за указанную дату, независимо от того, существует ли он.
+    pub fn path(&self, date: NaiveDate) -> PathBuf {
+        self.dir.join(Archive::file_name(date))
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.manifest.snapshots
+    }
+
+    pub fn snapshot(&self, date: NaiveDate) -> Option<&Snapshot> {
+        self.manifest.snapshots.iter().find(|x| x.date == date)
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.manifest.latest.and_then(|date| self.snapshot(date))
+    }
+
+    /// Добавляет снимок в архив (заменяя снимок за ту же дату) и делает его последним.
+    pub fn add(&mut self, snapshot: Snapshot) -> io::Result<()> {
+        self.manifest.latest = Some(snapshot.date);
+        self.manifest.snapshots.retain(|x| x.date != snapshot.date);
+        self.manifest.snapshots.push(snapshot);
+        self.manifest.snapshots.sort_by_key(|x| x.date);
+
+        self.save()
+    }
+
+    /// Удаляет снимки, не попадающие под политику хранения. Возвращает удаленные снимки.
+    pub fn prune(&mut self, retention: &Retention) -> io::Result<Vec<Snapshot>> {
+        let dates = self
+            .manifest
+            .snapshots
+            .iter()
+            .map(|x| x.date)
+            .collect::<Vec<_>>();
+        let retained = retention.retained(&dates);
+        let latest = self.manifest.latest;
+
+        let (kept, removed) = self
+            .manifest
+            .snapshots
+            .drain(..)
+            .partition(|x| retained.contains(&x.date) || Some(x.date) == latest);
+        self.manifest.snapshots = kept;
+        self.save()?;
+
+        for snapshot in &removed {
+            match fs::remove_file(self.dir.join(&snapshot.file)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let text = toml::to_string(&self.manifest)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST));
+
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, self.dir.join(MANIFEST))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Archive, Retention, Snapshot};
+    use crate::download::Validators;
+    use chrono::{Duration, NaiveDate};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn days(from: &str, count: i64) -> Vec<NaiveDate> {
+        (0..count).map(|x| date(from) + Duration::days(x)).collect()
+    }
+
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("zones-archive-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn retain_daily() {
+        let retention = Retention {
+            daily: 3,
+            weekly: 0,
+            monthly: 0,
+        };
+
+        let retained = retention.retained(&days("2026-10-01", 10));
+
+        assert_eq!(
+            days("2026-10-08", 3),
+            retained.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn retain_weekly_and_monthly() {
+        let retention = Retention {
+            daily: 1,
+            weekly: 2,
+            monthly: 2,
+        };
+
+        // С 2026-08-01 (суббота) по 2026-10-18 (воскресенье).
+        let retained = retention.retained(&days("2026-08-01", 79));
+
+        let expected = vec![
+            date("2026-09-30"), // последний день сентября
+            date("2026-10-11"), // воскресенье предыдущей недели
+            date("2026-10-18"), // последний день, неделя и месяц
+        ];
+        assert_eq!(expected, retained.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retain_latest_always() {
+        let retention = Retention {
+            daily: 0,
+            weekly: 0,
+            monthly: 0,
+        };
+
+        let retained = retention.retained(&days("2026-10-01", 3));
+
+        assert_eq!(
+            vec![date("2026-10-03")],
+            retained.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn add_and_reopen() {
+        let dir = dir("reopen");
+        let mut archive = Archive::open(&dir).unwrap();
+        let validators = Validators {
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+        };
+
+        archive
+            .add(Snapshot::new(date("2026-10-17"), 10, Validators::default()))
+            .unwrap();
+        archive
+            .add(Snapshot::new(date("2026-10-18"), 20, validators.clone()))
+            .unwrap();
+        let archive = Archive::open(&dir).unwrap();
+
+        let latest = archive.latest().unwrap();
+        assert_eq!(date("2026-10-18"), latest.date);
+        assert_eq!("2026-10-18.gz", latest.file);
+        assert_eq!(validators, latest.validators());
+        assert_eq!(2, archive.snapshots().len());
+        assert_eq!(dir.join("2026-10-17.gz"), archive.path(date("2026-10-17")));
+    }
+
+    #[test]
+    fn prune_removes_files() {
+        let dir = dir("prune");
+        let mut archive = Archive::open(&dir).unwrap();
+        for date in days("2026-10-01", 5) {
+            fs::write(archive.path(date), b"").unwrap();
+            archive
+                .add(Snapshot::new(date, 0, Validators::default()))
+                .unwrap();
+        }
+        let retention = Retention {
+            daily: 2,
+            weekly: 0,
+            monthly: 0,
+        };
+
+        let removed = archive.prune(&retention).unwrap();
+
+        assert_eq!(3, removed.len());
+        assert_eq!(2, archive.snapshots().len());
+        assert!(!archive.path(date("2026-10-03")).exists());
+        assert!(archive.path(date("2026-10-04")).exists());
+        assert!(archive.path(date("2026-10-05")).exists());
+    }
+}
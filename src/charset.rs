@@ -0,0 +1,193 @@
+use crate::media_type::MediaType;
+use encoding_rs::{Encoding, UTF_8};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Сколько первых байт страницы просматривается в поисках `<meta>` с кодировкой. Стандарт HTML
+/// ограничивается 1024 байтами, но на старых сайтах перед `<meta>` часто стоят длинные скрипты и
+/// комментарии.
+const PRESCAN: usize = 4096;
+
+/// Откуда взята кодировка страницы, в порядке убывания приоритета.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharsetSource {
+    /// Метка порядка байт в начале тела ответа.
+    Bom,
+    /// Параметр `charset` заголовка Content-Type.
+    Header,
+    /// `<meta charset="...">`.
+    Meta,
+    /// `<meta http-equiv="Content-Type" content="text/html; charset=...">`.
+    HttpEquiv,
+    /// Кодировка нигде не указана, страница декодирована как UTF-8.
+    Default,
+}
+
+impl Display for CharsetSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CharsetSource::Bom => write!(f, "bom"),
+            CharsetSource::Header => write!(f, "header"),
+            CharsetSource::Meta => write!(f, "meta"),
+            CharsetSource::HttpEquiv => write!(f, "http-equiv"),
+            CharsetSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// Декодированная страница.
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub source: CharsetSource,
+}
+
+impl Decoded {
+    /// Имя кодировки в нижнем регистре, например `windows-1251`.
+    pub fn charset(&self) -> String {
+        self.encoding.name().to_lowercase()
+    }
+}
+
+/// Декодирует HTML страницу. Кодировка определяется по метке порядка байт, заголовку
+/// Content-Type, `<meta charset>` и `<meta http-equiv="Content-Type">` в этом порядке, по
+/// умолчанию UTF-8. Неизвестные названия кодировок пропускаются.
+pub fn decode_html(content_type: Option<&str>, body: &[u8]) -> Decoded {
+    let (encoding, source) = if let Some((encoding, _)) = Encoding::for_bom(body) {
+        (encoding, CharsetSource::Bom)
+    } else if let Some(encoding) = content_type.and_then(header_charset) {
+        (encoding, CharsetSource::Header)
+    } else if let Some((encoding, source)) = meta_charset(body) {
+        (encoding, source)
+    } else {
+        (UTF_8, CharsetSource::Default)
+    };
+
+    Decoded {
+        text: encoding.decode_with_bom_removal(body).0.into_owned(),
+        encoding,
+        source,
+    }
+}
+
+/// Кодировка из параметра `charset` значения Content-Type.
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    let media = MediaType::parse(content_type)?;
+
+    Encoding::for_label(media.charset()?.as_bytes())
+}
+
+/// Кодировка из `<meta>` в начале страницы. Разметка до объявления кодировки состоит из ASCII,
+/// поэтому начало страницы можно разобрать, не зная кодировки.
+fn meta_charset(body: &[u8]) -> Option<(&'static Encoding, CharsetSource)> {
+    let prefix = String::from_utf8_lossy(&body[..body.len().min(PRESCAN)]);
+    let doc = Html::parse_document(&prefix);
+    let meta = Selector::parse("meta").unwrap();
+
+    let mut http_equiv = None;
+    for tag in doc.select(&meta) {
+        let tag = tag.value();
+
+        if let Some(label) = tag.attr("charset") {
+            if let Some(encoding) = Encoding::for_label(label.trim().as_bytes()) {
+                return Some((encoding.output_encoding(), CharsetSource::Meta));
+            }
+        }
+
+        let content_type = tag
+            .attr("http-equiv")
+            .filter(|x| x.eq_ignore_ascii_case("content-type"))
+            .and(tag.attr("content"));
+        if http_equiv.is_none() {
+            http_equiv = content_type.and_then(header_charset);
+        }
+    }
+
+    // UTF-16 в `<meta>` объявить нельзя: если бы страница была в UTF-16, разметка не читалась бы
+    // как ASCII. Такие объявления по стандарту означают UTF-8.
+    http_equiv.map(|x| (x.output_encoding(), CharsetSource::HttpEquiv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_html, CharsetSource};
+
+    /// «Привет» в windows-1251.
+    const CP1251: &[u8] = b"\xcf\xf0\xe8\xe2\xe5\xf2";
+    /// «Привет» в KOI8-R.
+    const KOI8: &[u8] = b"\xf0\xd2\xc9\xd7\xc5\xd4";
+
+    fn page(head: &str, title: &[u8]) -> Vec<u8> {
+        let mut page = format!("<html><head>{}<title>", head).into_bytes();
+        page.extend_from_slice(title);
+        page.extend_from_slice(b"</title></head></html>");
+        page
+    }
+
+    #[test]
+    fn header_charset() {
+        let decoded = decode_html(Some("text/html; Charset=\"windows-1251\""), CP1251);
+
+        assert_eq!("Привет", decoded.text);
+        assert_eq!("windows-1251", decoded.charset());
+        assert_eq!(CharsetSource::Header, decoded.source);
+    }
+
+    #[test]
+    fn meta_charset() {
+        let body = page(r#"<meta charset="KOI8-R">"#, KOI8);
+
+        let decoded = decode_html(Some("text/html"), &body);
+
+        assert!(decoded.text.contains("<title>Привет</title>"));
+        assert_eq!("koi8-r", decoded.charset());
+        assert_eq!(CharsetSource::Meta, decoded.source);
+    }
+
+    #[test]
+    fn http_equiv_charset() {
+        let body = page(
+            r#"<meta http-equiv="Content-Type" content="text/html; charset=windows-1251">"#,
+            CP1251,
+        );
+
+        let decoded = decode_html(None, &body);
+
+        assert!(decoded.text.contains("<title>Привет</title>"));
+        assert_eq!("windows-1251", decoded.charset());
+        assert_eq!(CharsetSource::HttpEquiv, decoded.source);
+
+        // Неизвестная кодировка в `<meta charset>` не мешает объявлению через http-equiv.
+        let body = page(
+            r#"<meta charset="unknown"><meta http-equiv="content-type" content="text/html; charset=koi8-r">"#,
+            KOI8,
+        );
+        assert_eq!("koi8-r", decode_html(None, &body).charset());
+    }
+
+    #[test]
+    fn priority() {
+        let body = page(r#"<meta charset="windows-1251">"#, "Привет".as_bytes());
+
+        // Заголовок важнее `<meta>`.
+        let decoded = decode_html(Some("text/html; charset=utf-8"), &body);
+        assert!(decoded.text.contains("<title>Привет</title>"));
+        assert_eq!(CharsetSource::Header, decoded.source);
+
+        // Метка порядка байт важнее заголовка.
+        let mut bom = b"\xef\xbb\xbf".to_vec();
+        bom.extend_from_slice(&body);
+        let decoded = decode_html(Some("text/html; charset=koi8-r"), &bom);
+        assert!(decoded.text.starts_with("<html>"));
+        assert_eq!("utf-8", decoded.charset());
+        assert_eq!(CharsetSource::Bom, decoded.source);
+
+        let decoded = decode_html(None, "Привет".as_bytes());
+        assert_eq!("Привет", decoded.text);
+        assert_eq!("utf-8", decoded.charset());
+        assert_eq!(CharsetSource::Default, decoded.source);
+    }
+}
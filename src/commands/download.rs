@@ -0,0 +1,32 @@
+use super::BoxResult;
+use ::zones::{Archive, Download, Downloader, Retention, Snapshot, Zone};
+use chrono::Local;
+use log::info;
+
+pub fn run(retention: &Retention, zones: &[&Zone]) -> BoxResult<()> {
+    let downloader = Downloader::default();
+    let today = Local::now().naive_local().date();
+
+    for zone in zones {
+        let mut archive = Archive::open(&zone.archive)?;
+        let known = archive
+            .latest()
+            .filter(|x| archive.dir().join(&x.file).exists())
+            .map(Snapshot::validators)
+            .unwrap_or_default();
+
+        match downloader.download(&zone.url, &archive.path(today), &known)? {
+            Download::NotModified => info!("Zone '{}' is up to date", zone.name),
+            Download::Downloaded { size, validators } => {
+                archive.add(Snapshot::new(today, size, validators))?;
+                info!("Zone '{}' snapshot {} saved", zone.name, today);
+            }
+        }
+
+        for snapshot in archive.prune(retention)? {
+            info!("Zone '{}' snapshot {} removed", zone.name, snapshot.date);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use super::{BoxResult, FIND_KEY, REMOVED};
+use ::zones::Zone;
+use bson::{doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::sync::Client;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    /// Одно доменное имя на строку.
+    Text,
+    /// Один документ из базы данных в формате JSON на строку.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Options {
+    /// Output format
+    #[structopt(long, default_value = "txt", possible_values = &["txt", "json"])]
+    format: Format,
+    /// Write to file instead of stdout
+    #[structopt(long, short, value_name = "path", parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Export removed domains too
+    #[structopt(long)]
+    with_removed: bool,
+}
+
+pub fn run(client: &Client, zones: &[&Zone], options: &Options) -> BoxResult<()> {
+    let output: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut writer = BufWriter::new(output);
+
+    let filter = if options.with_removed {
+        None
+    } else {
+        Some(doc! {REMOVED: {"$exists": false}})
+    };
+    let find_options = FindOptions::builder()
+        .sort(Some(doc! {FIND_KEY: 1}))
+        .build();
+
+    for zone in zones {
+        let coll = client.database(&zone.database).collection(&zone.collection);
+
+        for doc in coll.find(filter.clone(), Some(find_options.clone()))? {
+            let mut doc: Document = doc?;
+
+            match options.format {
+                Format::Text => {
+                    if let Ok(url) = doc.get_str(FIND_KEY) {
+                        writeln!(writer, "{}", url)?;
+                    }
+                }
+                Format::Json => {
+                    doc.remove("_id");
+                    writeln!(writer, "{}", Bson::Document(doc).into_relaxed_extjson())?;
+                }
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
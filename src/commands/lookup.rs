@@ -0,0 +1,1074 @@
+use super::{BoxResult, FIND_KEY, REMOVED};
+use ::zones::decode_html;
+use ::zones::field_name;
+use ::zones::parse_metadata;
+use ::zones::parse_title;
+use ::zones::DomainName;
+use ::zones::Site;
+use ::zones::Zone;
+use ::zones::{is_cross_domain, Failure, FailureReason, Lookup, RateLimiter, Redirect};
+use ::zones::{parse_redirect, RedirectKind};
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use mongodb::options::FindOptions;
+use mongodb::sync::Collection;
+use reqwest::header::{HeaderMap, CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::Response;
+use reqwest::StatusCode;
+use reqwest::Url;
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const HTTP: &str = "http://";
+const HTTPS: &str = "https://";
+/// Наибольшее число ошибок базы данных, после которого проверка зоны прекращается.
+const MAX_DB_ERRORS: usize = 10;
+/// Поле документа с арендой: какой процесс и до какого времени проверяет домен.
+const LEASE: &str = "lease";
+/// Поле документа со временем последней проверки.
+const LAST_CHECKED: &str = "last_checked";
+
+// 1. Берем из базы курсором пачки доменных имен без поля "lookup", а когда такие кончатся, с
+//    устаревшим результатом проверки, и захватываем каждое арендой.
+// 2. Стучимся по адресу по протоколам http и https, одновременно проверяется до `concurrency`
+//    доменов, частота запросов ограничена общим для всех зон ведром токенов.
+// 3. По каждому протоколу записываем ответ: url, title, charset, description, keywords. Url может
+//    отличаться от первоначального, если было перенаправление, шаги перенаправлений записываются
+//    в поля http_redirects и https_redirects, уход на другой домен отмечается cross_domain: true.
+//    Кроме ответов 3xx, проходятся перенаправления meta refresh и простыми скриптами.
+// 4. Сохраняем полученную информацию, код и заголовки ответа с отметкой lookup: true,
+//    success: true и временем проверки last_checked. Если хотя бы по одному протоколу сервер
+//    ответил кодом 200 и непустой страницей, отмечаем content: true.
+// 5. Если веб сервера на доменном имени нет, то отмечаем в базе lookup: true, success: false.
+//    Причина неудачи по каждому протоколу записывается в поля http_failure и https_failure.
+
+#[derive(Debug, StructOpt)]
+pub struct Options {
+    /// Check again already checked domains matching the filters below
+    #[structopt(long)]
+    rescan: bool,
+    /// Re-check only domains without a web server
+    #[structopt(long, requires = "rescan", conflicts_with = "succeeded")]
+    failed: bool,
+    /// Re-check only domains with a web server
+    #[structopt(long, requires = "rescan")]
+    succeeded: bool,
+    /// Re-check only domains checked before this date (YYYY-MM-DD)
+    #[structopt(long, value_name = "date", requires = "rescan")]
+    checked_before: Option<NaiveDate>,
+    /// Re-check only this domain (may be repeated)
+    #[structopt(
+        long = "domain",
+        value_name = "name",
+        number_of_values = 1,
+        requires = "rescan"
+    )]
+    domains: Vec<DomainName>,
+}
+
+impl Options {
+    /// Фильтр проверенных доменов, которые нужно проверить заново.
+    fn rescan_filter(&self) -> Option<Document> {
+        if !self.rescan {
+            return None;
+        }
+
+        let mut filter = doc! {"lookup": true, REMOVED: {"$exists": false}};
+        if self.failed {
+            filter.insert("success", doc! {"$ne": true});
+        }
+        if self.succeeded {
+            filter.insert("success", true);
+        }
+        if let Some(date) = self.checked_before {
+            let date = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+            filter.insert(
+                "$or",
+                vec![
+                    Bson::from(doc! {LAST_CHECKED: {"$lt": date}}),
+                    Bson::from(doc! {LAST_CHECKED: {"$exists": false}}),
+                ],
+            );
+        }
+        if !self.domains.is_empty() {
+            let domains = self
+                .domains
+                .iter()
+                .map(DomainName::as_str)
+                .collect::<Vec<_>>();
+            filter.insert(FIND_KEY, doc! {"$in": domains});
+        }
+
+        Some(filter)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Domain {
+    url: DomainName,
+    #[serde(default)]
+    http: Option<Site>,
+    #[serde(default)]
+    https: Option<Site>,
+    /// Перенаправления до страницы по каждому протоколу.
+    #[serde(default)]
+    http_redirects: Vec<Redirect>,
+    #[serde(default)]
+    https_redirects: Vec<Redirect>,
+    #[serde(default)]
+    http_failure: Option<Failure>,
+    #[serde(default)]
+    https_failure: Option<Failure>,
+    #[serde(default)]
+    lookup: bool,
+    /// Веб сервер ответил хотя бы по одному протоколу, с любым кодом.
+    #[serde(default)]
+    success: bool,
+    /// Веб сервер ответил кодом 200 и непустой страницей.
+    #[serde(default)]
+    content: bool,
+    /// Перенаправление хотя бы по одному протоколу увело на другой домен.
+    #[serde(default)]
+    cross_domain: bool,
+}
+
+impl Domain {
+    /// Сбрасывает результат предыдущей проверки.
+    fn reset(&mut self) {
+        self.http = None;
+        self.https = None;
+        self.http_failure = None;
+        self.https_failure = None;
+        self.http_redirects.clear();
+        self.https_redirects.clear();
+        self.success = false;
+        self.content = false;
+        self.cross_domain = false;
+    }
+
+    fn set_http_site(&mut self, site: Site) {
+        self.http = Some(site);
+        self.success = true;
+    }
+
+    fn set_https_site(&mut self, site: Site) {
+        self.https = Some(site);
+        self.success = true;
+    }
+}
+
+impl Display for Domain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "url: {}", self.url)?;
+
+        writeln!(f, "http:")?;
+        if let Some(ref http) = self.http {
+            writeln!(f, "    url: {}", http.url)?;
+            if let Some(status) = http.status {
+                writeln!(f, "    status: {}", status)?;
+            }
+            write!(f, "    title: ")?;
+            if let Some(ref title) = http.title {
+                writeln!(f, "{}", title)?;
+            } else {
+                writeln!(f)?;
+            }
+            write!(f, "    charset: ")?;
+            if let Some(ref charset) = http.charset {
+                write!(f, "{}", charset)?;
+                if let Some(source) = http.charset_source {
+                    write!(f, " ({})", source)?;
+                }
+                writeln!(f)?;
+            } else {
+                writeln!(f)?;
+            }
+            write!(f, "    description: ")?;
+            if let Some(ref desc) = http.description {
+                writeln!(f, "{}", desc)?;
+            } else {
+                writeln!(f)?;
+            }
+            write!(f, "    keywords: ")?;
+            if let Some(ref keywords) = http.keywords {
+                writeln!(f, "{}", keywords)?;
+            } else {
+                writeln!(f)?;
+            }
+        }
+
+        writeln!(f, "https:")?;
+        if let Some(ref https) = self.https {
+            writeln!(f, "    url: {}", https.url)?;
+            if let Some(status) = https.status {
+                writeln!(f, "    status: {}", status)?;
+            }
+            write!(f, "    title: ")?;
+            if let Some(ref title) = https.title {
+                writeln!(f, "{}", title)?;
+            } else {
+                writeln!(f)?;
+            }
+            write!(f, "    charset: ")?;
+            if let Some(ref charset) = https.charset {
+                write!(f, "{}", charset)?;
+                if let Some(source) = https.charset_source {
+                    write!(f, " ({})", source)?;
+                }
+                writeln!(f)?;
+            } else {
+                writeln!(f)?;
+            }
+            write!(f, "    description: ")?;
+            if let Some(ref desc) = https.description {
+                writeln!(f, "{}", desc)?;
+            } else {
+                writeln!(f)?;
+            }
+            write!(f, "    keywords: ")?;
+            if let Some(ref keywords) = https.keywords {
+                writeln!(f, "{}", keywords)?;
+            } else {
+                writeln!(f)?;
+            }
+        }
+
+        writeln!(f, "lookup: {}", self.lookup)?;
+        writeln!(f, "success: {}", self.success)?;
+        writeln!(f, "content: {}", self.content)
+    }
+}
+
+/// Читает тело ответа, прерывая чтение, если оно больше `limit` байт.
+async fn read_body(response: &mut Response, limit: usize) -> Result<Vec<u8>, Failure> {
+    let too_large = || {
+        Failure::new(
+            FailureReason::TooLarge,
+            format!("response body exceeds {} bytes", limit),
+        )
+    };
+
+    if response
+        .content_length()
+        .map_or(false, |x| x > limit as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            return Err(too_large());
+        }
+    }
+
+    Ok(body)
+}
+
+/// Заголовки ответа для записи в базу данных.
+fn collect_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut collected = BTreeMap::new();
+
+    for (name, value) in headers {
+        let name = field_name(name.as_str());
+        let value = String::from_utf8_lossy(value.as_bytes());
+
+        collected
+            .entry(name)
+            .and_modify(|x: &mut String| {
+                x.push_str(", ");
+                x.push_str(&value);
+            })
+            .or_insert_with(|| value.into_owned());
+    }
+
+    collected
+}
+
+/// Ответ по одному протоколу.
+#[derive(Debug, Default)]
+struct Probe {
+    /// Страница, если сервер ответил, с любым кодом.
+    site: Option<Site>,
+    /// Причина, по которой содержимое страницы не получено.
+    failure: Option<Failure>,
+    /// Сервер ответил кодом 200 и непустой страницей.
+    content: bool,
+    /// Перенаправления до страницы.
+    redirects: Vec<Redirect>,
+    /// Перенаправление увело на другой домен.
+    cross_domain: bool,
+    /// Адрес, на который перенаправляет ответ: из заголовка Location, meta refresh или скрипта.
+    redirect: Option<(RedirectKind, String)>,
+}
+
+impl Probe {
+    fn failed(failure: Failure) -> Self {
+        Probe {
+            failure: Some(failure),
+            ..Probe::default()
+        }
+    }
+}
+
+async fn dispatch(mut response: Response, max_body: usize) -> Probe {
+    let url = response.url().clone();
+    let status = response.status();
+    let headers = collect_headers(response.headers());
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(str::to_string);
+    let mut redirect = response
+        .headers()
+        .get(LOCATION)
+        .filter(|_| status.is_redirection())
+        .map(|x| {
+            (
+                RedirectKind::Http,
+                String::from_utf8_lossy(x.as_bytes()).into_owned(),
+            )
+        });
+
+    let body = read_body(&mut response, max_body).await;
+
+    // Метаинформацию разбираем и на страницах с ошибками: это могут быть заглушки хостинга или
+    // формы входа.
+    let mut site = match body {
+        Ok(ref body)
+            if content_type
+                .as_deref()
+                .map_or(true, |x| x.to_ascii_lowercase().contains("html")) =>
+        {
+            let decoded = decode_html(content_type.as_deref(), body);
+            let doc = Html::parse_document(&decoded.text);
+            let mut site = parse_metadata(&doc, Some(&url));
+
+            site.title = parse_title(&doc);
+            site.charset = Some(decoded.charset());
+            site.charset_source = Some(decoded.source);
+            redirect = redirect.or_else(|| parse_redirect(&doc));
+            site
+        }
+        _ => Site::default(),
+    };
+    site.url = url.to_string();
+    site.status = Some(i32::from(status.as_u16()));
+    site.headers = headers;
+
+    let (failure, content) = match body {
+        Err(failure) => (Some(failure), false),
+        Ok(_) if status != StatusCode::OK => (
+            Some(Failure::new(
+                FailureReason::HttpStatus {
+                    status: i32::from(status.as_u16()),
+                },
+                status.canonical_reason().unwrap_or_default(),
+            )),
+            false,
+        ),
+        Ok(body) => (None, !body.is_empty()),
+    };
+
+    Probe {
+        site: Some(site),
+        failure,
+        content,
+        redirect,
+        ..Probe::default()
+    }
+}
+
+/// Счетчики для периодического отчета о проверке зоны.
+#[derive(Debug, Default)]
+struct Stats {
+    checked: AtomicUsize,
+    requests: AtomicUsize,
+    errors: AtomicUsize,
+    db_errors: AtomicUsize,
+    lost: AtomicUsize,
+}
+
+impl Stats {
+    fn log(&self, zone: &str, elapsed: Duration) {
+        let checked = self.checked.load(Ordering::Relaxed);
+        let requests = self.requests.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+
+        info!(
+            "{} -- checked {} domains ({:.1}/s), {} of {} requests failed ({:.1}%), {} database errors, {} leases lost",
+            zone,
+            checked,
+            checked as f64 / elapsed.as_secs_f64().max(1.0),
+            errors,
+            requests,
+            errors as f64 * 100.0 / requests.max(1) as f64,
+            self.db_errors.load(Ordering::Relaxed),
+            self.lost.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// Обновление документа, которое выполняет задача записи в базу данных.
+#[derive(Debug)]
+struct Write {
+    query: Document,
+    update: Document,
+}
+
+impl Write {
+    /// Результат записывается, только если аренда домена все еще принадлежит процессу.
+    fn new(lease: Document, mut fields: Document) -> Self {
+        fields.insert(LAST_CHECKED, Utc::now());
+
+        Write {
+            query: lease,
+            update: doc! {"$set": fields, "$unset": {LEASE: ""}},
+        }
+    }
+}
+
+/// Выборка доменов для одного прохода и порядок их проверки.
+#[derive(Debug)]
+struct Selection {
+    filter: Document,
+    sort: Option<Document>,
+}
+
+/// Очередность проверки доменов зоны.
+///
+/// Сначала проверяются домены, которые еще не проверялись, начиная с недавно добавленных. Когда
+/// их не осталось, проверяются заново домены с устаревшим результатом: без веб сервера через
+/// `rescan_failed` дней, с веб сервером через `rescan_succeeded` дней после проверки.
+#[derive(Debug)]
+struct Schedule {
+    failed: Option<chrono::Duration>,
+    succeeded: Option<chrono::Duration>,
+}
+
+impl Schedule {
+    fn new(options: &Lookup) -> Self {
+        let days = |days: u64| {
+            Some(days)
+                .filter(|&x| x > 0)
+                .map(|x| chrono::Duration::days(x as i64))
+        };
+
+        Schedule {
+            failed: days(options.rescan_failed),
+            succeeded: days(options.rescan_succeeded),
+        }
+    }
+
+    /// Домены, которые еще не проверялись. Новые документы имеют большие `_id`, поэтому
+    /// сортировка по убыванию `_id` ставит недавно добавленные домены первыми.
+    fn unchecked() -> Selection {
+        Selection {
+            filter: doc! {"lookup": {"$exists": false}},
+            sort: Some(doc! {"_id": -1}),
+        }
+    }
+
+    /// Домены с устаревшим результатом проверки или `None`, если повторные проверки отключены.
+    fn stale(&self, now: DateTime<Utc>) -> Option<Selection> {
+        let mut due = vec![];
+
+        if let Some(failed) = self.failed {
+            due.push(Bson::from(
+                doc! {"success": {"$ne": true}, LAST_CHECKED: {"$lte": now - failed}},
+            ));
+        }
+        if let Some(succeeded) = self.succeeded {
+            due.push(Bson::from(
+                doc! {"success": true, LAST_CHECKED: {"$lte": now - succeeded}},
+            ));
+        }
+        if due.is_empty() {
+            return None;
+        }
+        // Домены, проверенные до появления времени проверки.
+        due.push(Bson::from(doc! {LAST_CHECKED: {"$exists": false}}));
+
+        Some(Selection {
+            filter: doc! {"lookup": true, "$or": due},
+            sort: None,
+        })
+    }
+}
+
+/// Аренда доменов зоны процессом.
+///
+/// Домен захватывается атомарно, поэтому несколько процессов, в том числе на разных машинах,
+/// могут проверять одну зону, не повторяя работу друг друга. Аренда упавшего процесса истекает,
+/// и домен захватывает другой процесс.
+#[derive(Debug)]
+struct Lease {
+    worker: String,
+    duration: chrono::Duration,
+}
+
+impl Lease {
+    fn new(options: &Lookup) -> Self {
+        Lease {
+            worker: options.worker_id(),
+            duration: chrono::Duration::seconds(options.lease as i64),
+        }
+    }
+
+    /// Домены из выборки `selection`, которые можно захватить: неудаленные, без аренды или с
+    /// истекшей арендой.
+    fn available(selection: &Document, now: DateTime<Utc>) -> Document {
+        doc! {
+            "$and": [
+                selection.clone(),
+                {REMOVED: {"$exists": false}},
+                {"$or": [{LEASE: {"$exists": false}}, {"lease.expires": {"$lte": now}}]},
+            ],
+        }
+    }
+
+    /// Захватывает домен. Возвращает документ и условие для записи результата или `None`, если
+    /// домен уже захватил другой процесс.
+    fn claim(
+        &self,
+        coll: &Collection,
+        selection: &Document,
+        id: Bson,
+    ) -> mongodb::error::Result<Option<(Document, Document)>> {
+        let now = Utc::now();
+        let expires = now + self.duration;
+
+        let mut filter = Lease::available(selection, now);
+        filter.insert("_id", id.clone());
+        let update = doc! {"$set": {LEASE: {"worker": &self.worker, "expires": expires}}};
+
+        let doc = coll.find_one_and_update(filter, update, None)?;
+        let held = doc! {"_id": id, "lease.worker": &self.worker, "lease.expires": expires};
+
+        Ok(doc.map(|doc| (doc, held)))
+    }
+}
+
+/// HTTP клиент и ограничения, общие для проверок доменов зоны.
+#[derive(Clone)]
+struct Prober {
+    www: reqwest::Client,
+    limiter: Arc<RateLimiter>,
+    /// Счетчики зоны: каждый отправленный запрос, в том числе по перенаправлению.
+    stats: Arc<Stats>,
+    /// Наибольшее число перенаправлений.
+    max_redirects: usize,
+    /// Наибольшее число перенаправлений meta refresh и скриптами.
+    max_html_redirects: usize,
+    /// Наибольший размер тела ответа в байтах.
+    max_body: usize,
+}
+
+impl Prober {
+    fn new(options: &Lookup, limiter: Arc<RateLimiter>, stats: Arc<Stats>) -> Self {
+        // Перенаправления обрабатываются вручную, чтобы записать каждый шаг.
+        let www = reqwest::ClientBuilder::default()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(10))
+            .redirect(Policy::none())
+            .build()
+            .unwrap();
+
+        Prober {
+            www,
+            limiter,
+            stats,
+            max_redirects: options.redirects,
+            max_html_redirects: options.html_redirects,
+            max_body: options.max_body * 1024 * 1024,
+        }
+    }
+
+    /// Запрашивает стартовую страницу домена по протоколу `proto` в отдельной задаче.
+    fn probe(&self, proto: &'static str, domain: &DomainName) -> JoinHandle<Probe> {
+        let url = Url::parse(&format!("{}{}", proto, domain)).unwrap();
+        tokio::spawn(self.clone().lookup_site(url, domain.clone()))
+    }
+
+    /// Запрашивает страницу по адресу `url` домена `domain`, проходя по перенаправлениям.
+    /// Каждый запрос, в том числе по перенаправлению, соблюдает ограничение частоты.
+    async fn lookup_site(self, mut url: Url, domain: DomainName) -> Probe {
+        let start = url.to_string();
+        let mut visited = HashSet::new();
+        let mut redirects = vec![];
+        let mut html_redirects = 0;
+        let mut cross_domain = false;
+        // Ответ на предыдущий шаг перенаправления.
+        let mut last: Option<Probe> = None;
+
+        let mut probe = loop {
+            self.limiter.acquire().await;
+            self.stats.requests.fetch_add(1, Ordering::Relaxed);
+            visited.insert(url.clone());
+
+            let time = Instant::now();
+            let response = match self.www.get(url.clone()).send().await {
+                Ok(response) => response,
+                // Если адрес перехода недоступен, результатом остается последний полученный
+                // ответ, а ошибка записывается как причина, по которой перенаправление не
+                // пройдено.
+                Err(err) => {
+                    let mut probe = last.take().unwrap_or_default();
+                    probe.failure = Some(err.into());
+                    break probe;
+                }
+            };
+            let status = i32::from(response.status().as_u16());
+            let mut probe = dispatch(response, self.max_body).await;
+
+            // Перенаправления из HTML проходятся, только пока не исчерпан их отдельный лимит.
+            let (kind, location) = match probe.redirect.take() {
+                Some((kind, location))
+                    if kind == RedirectKind::Http || html_redirects < self.max_html_redirects =>
+                {
+                    (kind, location)
+                }
+                _ => break probe,
+            };
+            let next = match url.join(&location) {
+                Ok(next) => next,
+                Err(_) => break probe,
+            };
+            // Страница, которая обновляет сама себя, загружена, это не петля перенаправлений.
+            if kind != RedirectKind::Http && next == url {
+                break probe;
+            }
+            if kind != RedirectKind::Http {
+                html_redirects += 1;
+            }
+
+            redirects.push(Redirect {
+                url: url.to_string(),
+                status,
+                location: Some(location),
+                time: time.elapsed().as_millis() as i64,
+                kind,
+            });
+            cross_domain |= is_cross_domain(&domain, &next);
+
+            let failure = if visited.contains(&next) {
+                FailureReason::RedirectLoop
+            } else if redirects.len() > self.max_redirects {
+                FailureReason::TooManyRedirects
+            } else {
+                url = next;
+                last = Some(probe);
+                continue;
+            };
+
+            probe.failure = Some(Failure::new(failure, next.to_string()));
+            break probe;
+        };
+        probe.redirects = redirects;
+        probe.cross_domain = cross_domain;
+
+        match probe.failure {
+            Some(ref failure) if probe.site.is_some() => warn!("{} -- {}", start, failure),
+            Some(ref failure) => error!("{} -- FAILED -- {}", start, failure),
+            None => {}
+        }
+
+        probe
+    }
+}
+
+async fn lookup_domain(
+    prober: &Prober,
+    mut domain: Domain,
+    lease: Document,
+    stats: &Stats,
+) -> Write {
+    domain.reset();
+
+    let http = prober.probe(HTTP, &domain.url);
+    let https = prober.probe(HTTPS, &domain.url);
+
+    let (http, https) = tokio::join!(http, https);
+
+    let failed = |err: tokio::task::JoinError| {
+        Probe::failed(Failure::new(FailureReason::Other, err.to_string()))
+    };
+    let (http, https) = (http.unwrap_or_else(failed), https.unwrap_or_else(failed));
+
+    for probe in [&http, &https] {
+        if probe.failure.is_some() {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    domain.content = http.content || https.content;
+    domain.cross_domain = http.cross_domain || https.cross_domain;
+    domain.http_redirects = http.redirects;
+    domain.https_redirects = https.redirects;
+    domain.http_failure = http.failure;
+    domain.https_failure = https.failure;
+
+    if let Some(site) = http.site {
+        domain.set_http_site(site);
+    }
+    if let Some(site) = https.site {
+        domain.set_https_site(site);
+    }
+
+    domain.lookup = true;
+    stats.checked.fetch_add(1, Ordering::Relaxed);
+
+    if domain.success {
+        debug!("Look up domain {}\n{}", domain.url, domain);
+    }
+
+    Write::new(lease, bson::to_document(&domain).unwrap())
+}
+
+async fn check_document(prober: &Prober, doc: Document, lease: Document, stats: &Stats) -> Write {
+    let url = doc.get(FIND_KEY).cloned();
+
+    match bson::from_bson::<Domain>(Bson::Document(doc)) {
+        Ok(domain) => lookup_domain(prober, domain, lease, stats).await,
+        // Документ с некорректным именем отмечаем проверенным, чтобы не выбирать его снова.
+        Err(err) => {
+            warn!("{:?} -- {}", url, err);
+
+            Write::new(lease, doc! {"lookup": true, "success": false})
+        }
+    }
+}
+
+async fn write_results(coll: Collection, mut writes: mpsc::Receiver<Write>, stats: Arc<Stats>) {
+    while let Some(write) = writes.recv().await {
+        let Write { query, update } = write;
+        let result = tokio::task::block_in_place(|| coll.update_one(query.clone(), update, None));
+
+        match result {
+            Ok(result) if result.matched_count == 0 => {
+                warn!("{} -- lease expired, result dropped", query);
+                stats.lost.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn!("{}", err);
+                stats.db_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Один проход курсором по выборке доменов зоны. Возвращает число полученных документов.
+///
+/// Перед проверкой домен захватывается арендой, домены, захваченные другими процессами,
+/// пропускаются. Домены проверяются не более чем по `concurrency` одновременно, результаты
+/// передаются через очередь задаче записи. Когда запись отстает и очередь заполняется, проверка
+/// новых доменов приостанавливается.
+async fn lookup_pass(
+    prober: &Prober,
+    coll: &Collection,
+    options: &Lookup,
+    lease: &Lease,
+    stats: &Arc<Stats>,
+    selection: Selection,
+) -> mongodb::error::Result<usize> {
+    let find = FindOptions::builder()
+        .batch_size(Some(options.batch))
+        .projection(doc! {"_id": 1})
+        .sort(selection.sort)
+        .build();
+    let selection = &selection.filter;
+    let cursor = coll.find(Lease::available(selection, Utc::now()), find)?;
+
+    let (sender, receiver) = mpsc::channel(options.writes.max(1));
+    let writer = tokio::spawn(write_results(coll.clone(), receiver, stats.clone()));
+    let records = AtomicUsize::new(0);
+
+    // Курсор синхронный: getMore блокирует поток, поэтому, как и захват аренды, каждый шаг
+    // курсора выполняется в block_in_place.
+    let docs = futures::stream::unfold(cursor, |mut cursor| async move {
+        match tokio::task::block_in_place(|| cursor.next())? {
+            Ok(doc) => Some((doc, cursor)),
+            Err(err) => {
+                error!("{}", err);
+                stats.db_errors.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    });
+
+    docs.for_each_concurrent(options.concurrency.max(1), |doc| {
+        let mut sender = sender.clone();
+        let records = &records;
+
+        async move {
+            records.fetch_add(1, Ordering::Relaxed);
+
+            let id = doc.get("_id").cloned().unwrap_or(Bson::Null);
+            let (doc, held) = match tokio::task::block_in_place(|| lease.claim(coll, selection, id))
+            {
+                Ok(Some(claimed)) => claimed,
+                Ok(None) => return,
+                Err(err) => {
+                    error!("{}", err);
+                    stats.db_errors.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            };
+
+            let write = check_document(prober, doc, held, stats).await;
+            // Ждет, если очередь записи заполнена.
+            let _ = sender.send(write).await;
+        }
+    })
+    .await;
+
+    drop(sender);
+    let _ = writer.await;
+
+    Ok(records.into_inner())
+}
+
+async fn report(zone: &str, stats: &Stats, start: Instant, period: Duration) {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+        stats.log(zone, start.elapsed());
+    }
+}
+
+/// Помечает выбранные проверенные домены непроверенными.
+fn schedule_rescan(coll: &Collection, zone: &str, filter: Document) -> mongodb::error::Result<()> {
+    let result = coll.update_many(filter, doc! {"$unset": {"lookup": ""}}, None)?;
+    info!(
+        "{} -- {} domains scheduled for re-check",
+        zone, result.modified_count
+    );
+
+    Ok(())
+}
+
+async fn lookup_sites(
+    client: mongodb::sync::Client,
+    zone: Zone,
+    options: Lookup,
+    limiter: Arc<RateLimiter>,
+    rescan: Option<Document>,
+) {
+    let stats = Arc::new(Stats::default());
+    let prober = Prober::new(&options, limiter, stats.clone());
+    let db = client.database(&zone.database);
+    let coll = db.collection(&zone.collection);
+    let lease = Lease::new(&options);
+    let schedule = Schedule::new(&options);
+    let start = Instant::now();
+
+    if let Some(filter) = rescan {
+        if let Err(err) = schedule_rescan(&coll, &zone.name, filter) {
+            error!("{} -- {}", zone.name, err);
+            return;
+        }
+    }
+
+    let work = async {
+        loop {
+            // Документы, которые не удалось записать, попадут в следующий проход. Новые домены
+            // выбираются заново после каждого прохода по устаревшим.
+            let mut records = lookup_pass(
+                &prober,
+                &coll,
+                &options,
+                &lease,
+                &stats,
+                Schedule::unchecked(),
+            )
+            .await;
+            if let (Ok(0), Some(stale)) = (&records, schedule.stale(Utc::now())) {
+                records = lookup_pass(&prober, &coll, &options, &lease, &stats, stale).await;
+            }
+
+            match records {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    error!("{}", err);
+                    stats.db_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            // Если что-то случилось с БД.
+            if stats.db_errors.load(Ordering::Relaxed) >= MAX_DB_ERRORS {
+                error!("{} -- too many database errors, stop", zone.name);
+                break;
+            }
+        }
+    };
+    let period = Duration::from_secs(options.report.max(1));
+
+    tokio::select! {
+        _ = work => {}
+        _ = report(&zone.name, &stats, start, period) => {}
+    }
+
+    stats.log(&zone.name, start.elapsed());
+}
+
+pub async fn run(
+    client: mongodb::sync::Client,
+    config: &Lookup,
+    zones: &[&Zone],
+    options: &Options,
+) -> BoxResult<()> {
+    // Ограничение частоты запросов общее для всех зон.
+    let limiter = Arc::new(config.limiter());
+    let rescan = options.rescan_filter();
+    let tasks = zones
+        .iter()
+        .map(|zone| {
+            tokio::spawn(lookup_sites(
+                client.clone(),
+                (*zone).clone(),
+                config.clone(),
+                limiter.clone(),
+                rescan.clone(),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    for result in futures::future::join_all(tasks).await {
+        result?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_headers, Options, Prober, Schedule, Stats, LAST_CHECKED};
+    use ::zones::{FailureReason, Lookup, RateLimiter};
+    use bson::{doc, Bson};
+    use chrono::{DateTime, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue, SERVER, SET_COOKIE};
+    use reqwest::Url;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+    use structopt::StructOpt;
+
+    #[test]
+    fn rescan_filter() {
+        let options =
+            Options::from_iter(&["lookup", "--rescan", "--failed", "--domain", "Пример.РФ"]);
+
+        let filter = options.rescan_filter().unwrap();
+
+        assert_eq!(Ok(true), filter.get_bool("lookup"));
+        assert_eq!(&doc! {"$ne": true}, filter.get_document("success").unwrap());
+        assert_eq!(
+            &doc! {"$in": ["xn--e1afmkfd.xn--p1ai"]},
+            filter.get_document("url").unwrap()
+        );
+        assert!(Options::from_iter(&["lookup"]).rescan_filter().is_none());
+    }
+
+    #[test]
+    fn join_repeated_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, HeaderValue::from_static("nginx"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+        headers.insert("x.powered.by", HeaderValue::from_static("PHP"));
+
+        let headers = collect_headers(&headers);
+
+        assert_eq!("nginx", headers["server"]);
+        assert_eq!("a=1, b=2", headers["set-cookie"]);
+        assert_eq!("PHP", headers["x_powered_by"]);
+    }
+
+    #[test]
+    fn stale_selection() {
+        let now = "2026-10-18T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let options = Lookup {
+            rescan_failed: 7,
+            rescan_succeeded: 0,
+            ..Lookup::default()
+        };
+
+        let stale = Schedule::new(&options).stale(now).unwrap();
+
+        let due = stale.filter.get_array("$or").unwrap();
+        assert_eq!(2, due.len());
+        assert_eq!(
+            Bson::from(doc! {
+                "success": {"$ne": true},
+                LAST_CHECKED: {"$lte": "2026-10-11T00:00:00Z".parse::<DateTime<Utc>>().unwrap()},
+            }),
+            due[0]
+        );
+
+        let options = Lookup {
+            rescan_failed: 0,
+            ..options
+        };
+        assert!(Schedule::new(&options).stale(now).is_none());
+    }
+
+    #[tokio::test]
+    async fn keep_response_when_redirect_target_refuses() {
+        // Порт, на котором никто не слушает: соединение с ним отклоняется.
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("http://{}/", closed.local_addr().unwrap());
+        drop(closed);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let location = target.clone();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let response = format!(
+                "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n",
+                location
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        let prober = Prober::new(
+            &Lookup::default(),
+            Arc::new(RateLimiter::new(100.0, 10)),
+            Arc::new(Stats::default()),
+        );
+        let probe = prober
+            .lookup_site(url.clone(), "example.ru".parse().unwrap())
+            .await;
+
+        assert_eq!(Some(301), probe.site.and_then(|x| x.status));
+        assert_eq!(1, probe.redirects.len());
+        assert_eq!(url.as_str(), probe.redirects[0].url);
+        assert_eq!(Some(target), probe.redirects[0].location);
+        assert_eq!(
+            Some(FailureReason::Refused),
+            probe.failure.map(|x| x.reason)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+pub mod download;
+pub mod export;
+pub mod lookup;
+pub mod report;
+pub mod resolve;
+pub mod stats;
+pub mod update;
+
+use ::zones::{Archive, Zone};
+use chrono::NaiveDate;
+use std::path::PathBuf;
+
+pub type BoxResult<T, E = Box<dyn std::error::Error + Send + Sync>> = Result<T, E>;
+
+/// Поле документа с доменным именем в канонической форме (ASCII, punycode).
+pub const FIND_KEY: &str = "url";
+/// Поле документа с доменным именем в Unicode.
+pub const UNICODE_KEY: &str = "unicode";
+/// Поле документа, которым помечаются домены, удаленные из реестра.
+pub const REMOVED: &str = "removed";
+
+/// Дата и путь к снимку зоны за указанную дату или к последнему снимку.
+pub fn find_snapshot(zone: &Zone, date: Option<NaiveDate>) -> BoxResult<(NaiveDate, PathBuf)> {
+    let archive = Archive::open(&zone.archive)?;
+    let snapshot = match date {
+        Some(date) => archive
+            .snapshot(date)
+            .ok_or_else(|| format!("zone '{}' has no snapshot for {}", zone.name, date))?,
+        None => archive
+            .latest()
+            .ok_or_else(|| format!("zone '{}' has no snapshots, run download first", zone.name))?,
+    };
+
+    Ok((snapshot.date, archive.dir().join(&snapshot.file)))
+}
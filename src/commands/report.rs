@@ -0,0 +1,147 @@
+use super::update::Changes;
+use super::BoxResult;
+use ::zones::DomainName;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Counts {
+    pub added: usize,
+    pub removed: usize,
+    pub re_added: usize,
+    pub changed: usize,
+    pub malformed: usize,
+}
+
+/// Отчет о различиях между снимком реестра и базой данных зоны.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub zone: String,
+    pub snapshot: NaiveDate,
+    pub counts: Counts,
+    pub added: Vec<DomainName>,
+    pub removed: Vec<DomainName>,
+}
+
+impl Report {
+    pub fn new(zone: &str, changes: &Changes) -> Self {
+        Report {
+            zone: zone.into(),
+            snapshot: changes.snapshot,
+            counts: changes.counts.clone(),
+            added: changes.added_domains.clone().unwrap_or_default(),
+            removed: changes.removed.clone(),
+        }
+    }
+
+    fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let counts = &self.counts;
+
+        writeln!(writer, "zone: {}", self.zone)?;
+        writeln!(writer, "snapshot: {}", self.snapshot)?;
+        writeln!(writer, "added: {}", counts.added)?;
+        writeln!(writer, "removed: {}", counts.removed)?;
+        writeln!(writer, "re-added: {}", counts.re_added)?;
+        writeln!(writer, "changed: {}", counts.changed)?;
+        writeln!(writer, "malformed: {}", counts.malformed)?;
+
+        writeln!(writer, "\nadded domains:")?;
+        for domain in &self.added {
+            writeln!(writer, "{}", domain)?;
+        }
+        writeln!(writer, "\nremoved domains:")?;
+        for domain in &self.removed {
+            writeln!(writer, "{}", domain)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Записывает отчеты по зонам в файл или, если он не указан, в stdout.
+pub fn write(reports: &[Report], path: Option<&Path>, format: ReportFormat) -> BoxResult<()> {
+    let output: Box<dyn Write> = match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut writer = BufWriter::new(output);
+
+    match format {
+        ReportFormat::Text => {
+            for (index, report) in reports.iter().enumerate() {
+                if index > 0 {
+                    writeln!(writer)?;
+                }
+                report.write_text(&mut writer)?;
+            }
+        }
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, reports)?;
+            writeln!(writer)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Counts, Report};
+    use chrono::NaiveDate;
+
+    fn report() -> Report {
+        Report {
+            zone: "ru".into(),
+            snapshot: NaiveDate::from_ymd_opt(2026, 10, 18).unwrap(),
+            counts: Counts {
+                added: 1,
+                removed: 2,
+                ..Counts::default()
+            },
+            added: vec!["NEW.RU".parse().unwrap()],
+            removed: vec!["OLD.RU".parse().unwrap(), "GONE.RU".parse().unwrap()],
+        }
+    }
+
+    #[test]
+    fn text_report() {
+        let mut output = vec![];
+        report().write_text(&mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+
+        assert!(text.starts_with("zone: ru\nsnapshot: 2026-10-18\nadded: 1\nremoved: 2\n"));
+        assert!(text.ends_with("added domains:\nnew.ru\n\nremoved domains:\nold.ru\ngone.ru\n"));
+    }
+
+    #[test]
+    fn json_report() {
+        let value = serde_json::to_value(report()).unwrap();
+
+        assert_eq!("2026-10-18", value["snapshot"]);
+        assert_eq!(2, value["counts"]["removed"]);
+        assert_eq!("old.ru", value["removed"][0]);
+    }
+}
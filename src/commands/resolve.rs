@@ -1,7 +1,11 @@
-use flate2::read::GzDecoder;
+use super::{find_snapshot, BoxResult};
+use ::zones::{DomainName, RegistryError, RegistryReader, Zone};
+use flate2::read::MultiGzDecoder;
+use log::warn;
 use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use trust_dns_resolver::config::*;
 use trust_dns_resolver::Resolver;
 
@@ -40,39 +44,48 @@ fn create_resolver() -> io::Result<Resolver> {
     Resolver::new(config, options)
 }
 
-fn extract_domains(from: &str, to: &str) -> io::Result<()> {
+fn extract_domains(from: &Path, to: &Path) -> io::Result<()> {
     let input = File::open(from)?;
     let output = File::create(to)?;
-    let decoder = GzDecoder::new(input);
+    let decoder = MultiGzDecoder::new(input);
     let reader = BufReader::new(decoder);
     let mut writer = BufWriter::new(output);
 
-    reader
-        .lines()
-        .filter_map(|x| x.ok())
-        .map(|s| s.split_ascii_whitespace().take(1).collect::<String>())
-        .for_each(|domain| {
-            writer.write_fmt(format_args!("{}\n", domain)).unwrap();
-        });
+    for result in RegistryReader::new(reader) {
+        match result {
+            Ok(record) => writer.write_fmt(format_args!("{}\n", record.domain))?,
+            Err(RegistryError::Io(err)) => return Err(err),
+            Err(err) => warn!("{} -- {}", from.display(), err),
+        }
+    }
 
     Ok(())
 }
 
-fn resolve_domains(unresolved: &str, resolved: &str) -> io::Result<()> {
+fn resolve_domains(unresolved: &Path, resolved: &Path) -> io::Result<()> {
     let resolver = create_resolver()?;
     let input = File::open(unresolved)?;
     let output = File::create(resolved)?;
     let reader = BufReader::new(input);
     let mut writer = BufWriter::new(output);
-    let mut unr_writer = BufWriter::new(File::create("zones/unresolved.ru.tmp")?);
+    let mut tmp = unresolved.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut unr_writer = BufWriter::new(File::create(&tmp)?);
 
     reader
         .lines()
-        .filter_map(|x| x.ok())
+        .map_while(Result::ok)
+        .filter_map(|line| match line.parse::<DomainName>() {
+            Ok(domain) => Some(domain),
+            Err(err) => {
+                warn!("{} -- {}", unresolved.display(), err);
+                None
+            }
+        })
         .take(1000)
-        .for_each(|domain| match resolver.lookup_ip(&domain) {
+        .for_each(|domain| match resolver.lookup_ip(domain.as_str()) {
             Ok(ips) => {
-                writer.write_all(domain.as_bytes()).unwrap();
+                writer.write_all(domain.as_str().as_bytes()).unwrap();
                 print!("{}", &domain);
                 for ip in ips {
                     writer.write_fmt(format_args!(" {}", &ip)).unwrap();
@@ -87,22 +100,41 @@ fn resolve_domains(unresolved: &str, resolved: &str) -> io::Result<()> {
             }
         });
 
-    fs::copy("zones/unresolved.ru.tmp", "zones/unresolved.ru")?;
-    fs::remove_file("zones/unresolved.ru.tmp")?;
+    drop(unr_writer);
+    fs::copy(&tmp, unresolved)?;
+    fs::remove_file(&tmp)?;
 
     Ok(())
 }
 
-fn main() -> io::Result<()> {
+/// Рабочие файлы зоны лежат в каталоге ее снимков, например `zones/ru/resolved.ru`.
+fn work_file(zone: &Zone, kind: &str) -> PathBuf {
+    zone.archive.join(format!("{}.{}", kind, zone.name))
+}
+
+fn resolve_zone(zone: &Zone, snapshot: &Path) -> io::Result<()> {
+    let domains = work_file(zone, "domains");
+    let unresolved = work_file(zone, "unresolved");
+    let resolved = work_file(zone, "resolved");
+
     loop {
-        if fs::metadata("zones/unresolved.ru").is_ok() {
-            resolve_domains("zones/unresolved.ru", "zones/resolved.ru")?;
+        if fs::metadata(&unresolved).is_ok() {
+            resolve_domains(&unresolved, &resolved)?;
             break;
         } else {
-            extract_domains("zones/ru_domains.gz", "zones/domains.ru")?;
-            fs::copy("zones/domains.ru", "zones/unresolved.ru")?;
+            extract_domains(snapshot, &domains)?;
+            fs::copy(&domains, &unresolved)?;
         }
     }
 
     Ok(())
 }
+
+pub fn run(zones: &[&Zone]) -> BoxResult<()> {
+    for zone in zones {
+        let (_, path) = find_snapshot(zone, None)?;
+        resolve_zone(zone, &path)?;
+    }
+
+    Ok(())
+}
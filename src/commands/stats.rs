@@ -0,0 +1,28 @@
+use super::{BoxResult, REMOVED};
+use ::zones::Zone;
+use bson::doc;
+use mongodb::sync::Client;
+
+pub fn run(client: &Client, zones: &[&Zone]) -> BoxResult<()> {
+    println!(
+        "{:<8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "zone", "domains", "removed", "lookup", "success", "content"
+    );
+
+    for zone in zones {
+        let coll = client.database(&zone.database).collection(&zone.collection);
+
+        let total = coll.count_documents(None, None)?;
+        let removed = coll.count_documents(doc! {REMOVED: true}, None)?;
+        let lookup = coll.count_documents(doc! {"lookup": true}, None)?;
+        let success = coll.count_documents(doc! {"success": true}, None)?;
+        let content = coll.count_documents(doc! {"content": true}, None)?;
+
+        println!(
+            "{:<8} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            zone.name, total, removed, lookup, success, content
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,991 @@
+use super::report::{self, Counts, Report, ReportFormat};
+use super::{find_snapshot, BoxResult, FIND_KEY, REMOVED, UNICODE_KEY};
+use ::zones::{
+    ascending, is_sorted, keyed_diff, Change, Config, DomainError, DomainName, ExternalSort, Guard,
+    Progress, RegistryError, RegistryReader, RegistryRecord, Side, Zone,
+};
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use flate2::read::MultiGzDecoder;
+use log::{error, info, warn};
+use mongodb::error::{BulkWriteFailure, ErrorKind};
+use mongodb::options::{FindOptions, InsertManyOptions};
+use mongodb::sync::{Client, Collection, Database};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+const MONGODB_CHUNK_SIZE: usize = 100_000;
+/// Размер списка доменов в запросе `$in` и число обновлений в одной команде `update`.
+const MONGODB_UPDATE_CHUNK_SIZE: usize = 10_000;
+
+/// Коллекция с историей изменений доменов.
+const EVENTS: &str = "events";
+
+// Поля документа домена с датами изменений.
+const FIRST_SEEN: &str = "first_seen";
+const REMOVED_AT: &str = "removed_at";
+const RE_ADDED_AT: &str = "re_added_at";
+
+// Типы событий.
+const ADDED_EVENT: &str = "first_seen";
+const REMOVED_EVENT: &str = "removed";
+const RE_ADDED_EVENT: &str = "re_added";
+const REGISTRAR_EVENT: &str = "registrar_change";
+const PAID_TILL_EVENT: &str = "paid_till_change";
+
+#[derive(Debug, StructOpt)]
+pub struct Options {
+    /// Replay the snapshot of this date (YYYY-MM-DD) instead of the latest one
+    #[structopt(long, value_name = "date")]
+    snapshot: Option<NaiveDate>,
+    /// Don't write to the database, only report what would change
+    #[structopt(long)]
+    dry_run: bool,
+    /// Write the diff report to file (stdout for --dry-run by default)
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    report: Option<PathBuf>,
+    /// Diff report format
+    #[structopt(long, value_name = "format", default_value = "txt", possible_values = &["txt", "json"])]
+    report_format: ReportFormat,
+    /// Apply the snapshot even if it looks truncated or removes too many domains
+    #[structopt(long)]
+    force: bool,
+}
+
+/// Домен с колонками реестра в том виде, в котором он хранится в базе данных.
+///
+/// Колонки могут отсутствовать у доменов, добавленных в базу до того, как они стали
+/// сохраняться. Записи реестра приводятся к тому же виду, чтобы сравнивать их целиком.
+#[derive(Debug, PartialEq)]
+struct Entry {
+    url: DomainName,
+    unicode: Option<String>,
+    removed: bool,
+    registrar: Option<String>,
+    created: Option<DateTime<Utc>>,
+    paid_till: Option<DateTime<Utc>>,
+    free_date: Option<DateTime<Utc>>,
+    delegated: Option<bool>,
+}
+
+impl Entry {
+    fn fields(&self) -> Document {
+        let value = |x: Option<DateTime<Utc>>| x.map_or(Bson::Null, Bson::from);
+
+        doc! {
+            UNICODE_KEY: self.unicode.as_deref().map_or(Bson::Null, Bson::from),
+            "registrar": self.registrar.as_deref().map_or(Bson::Null, Bson::from),
+            "created": value(self.created),
+            "paid_till": value(self.paid_till),
+            "free_date": value(self.free_date),
+            "delegated": self.delegated.map_or(Bson::Null, Bson::from),
+        }
+    }
+}
+
+impl TryFrom<Document> for Entry {
+    type Error = DomainError;
+
+    fn try_from(doc: Document) -> Result<Self, Self::Error> {
+        let date = |key| doc.get_datetime(key).ok().cloned();
+
+        Ok(Entry {
+            url: doc.get_str(FIND_KEY).unwrap_or_default().parse()?,
+            unicode: doc.get_str(UNICODE_KEY).ok().map(String::from),
+            removed: doc.get_bool(REMOVED).unwrap_or_default(),
+            registrar: doc.get_str("registrar").ok().map(String::from),
+            created: date("created"),
+            paid_till: date("paid_till"),
+            free_date: date("free_date"),
+            delegated: doc.get_bool("delegated").ok(),
+        })
+    }
+}
+
+impl From<RegistryRecord> for Entry {
+    fn from(record: RegistryRecord) -> Self {
+        Entry {
+            unicode: Some(record.domain.to_unicode()),
+            url: record.domain,
+            removed: false,
+            registrar: Some(record.registrar),
+            created: Some(to_datetime(record.created)),
+            paid_till: Some(to_datetime(record.paid_till)),
+            free_date: record.free_date.map(to_datetime),
+            delegated: Some(record.delegated),
+        }
+    }
+}
+
+fn to_datetime(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+/// Коллекции, в которые записываются изменения. При `--dry-run` изменения только подсчитываются.
+struct Target {
+    db: Database,
+    coll: Collection,
+    events: Collection,
+    /// Ход записи добавленных доменов, измененных доменов и событий за все пачки.
+    added: Progress,
+    changed: Progress,
+    inserted_events: Progress,
+}
+
+impl Target {
+    fn new(db: Database, zone: &Zone) -> Self {
+        Target {
+            coll: db.collection(&zone.collection),
+            events: db.collection(EVENTS),
+            db,
+            added: Progress::new(format!("Add domains to zone '{}'", zone.name), None),
+            changed: Progress::new(format!("Change domains in zone '{}'", zone.name), None),
+            inserted_events: Progress::new(format!("Log events of zone '{}'", zone.name), None),
+        }
+    }
+}
+
+/// Изменения базы данных, полученные при сравнении снимка реестра с базой данных.
+///
+/// Добавленные и измененные домены и их события накапливаются в буферах и записываются
+/// пачками по мере сравнения, поэтому расход памяти не зависит от размера зоны. Удаление
+/// доменов откладывается до проверки снимка (см. [`check_changes`]).
+pub struct Changes {
+    pub snapshot: NaiveDate,
+    target: Option<Target>,
+    added: Vec<Document>,
+    updated: Vec<(DomainName, Document)>,
+    events: Vec<Document>,
+    /// Имена добавленных доменов, собираются только для отчета.
+    pub added_domains: Option<Vec<DomainName>>,
+    /// Домены, пропавшие из реестра.
+    pub removed: Vec<DomainName>,
+    pub counts: Counts,
+    /// Число разобранных записей снимка.
+    pub records: u64,
+    /// Число неудаленных доменов в базе данных до обновления.
+    pub stored: u64,
+    summary: Summary,
+}
+
+impl Changes {
+    fn new(snapshot: NaiveDate, target: Option<Target>, collect_added: bool) -> Self {
+        Changes {
+            snapshot,
+            target,
+            added: vec![],
+            updated: vec![],
+            events: vec![],
+            added_domains: if collect_added { Some(vec![]) } else { None },
+            removed: vec![],
+            counts: Counts::default(),
+            records: 0,
+            stored: 0,
+            summary: Summary::default(),
+        }
+    }
+
+    fn event(&mut self, domain: &DomainName, kind: &str, details: Document) {
+        let mut event = doc! {
+            "domain": domain.as_str(),
+            "type": kind,
+            "snapshot": self.snapshot.format("%Y-%m-%d").to_string(),
+            "at": Utc::now(),
+        };
+        event.extend(details);
+
+        self.events.push(event);
+    }
+
+    /// Домен появился в реестре впервые.
+    fn add(&mut self, entry: Entry) {
+        self.counts.added += 1;
+        self.event(&entry.url, ADDED_EVENT, Document::new());
+
+        let mut doc = doc! {FIND_KEY: entry.url.as_str(), FIRST_SEEN: to_datetime(self.snapshot)};
+        doc.extend(entry.fields());
+        self.added.push(doc);
+
+        if let Some(ref mut domains) = self.added_domains {
+            domains.push(entry.url);
+        }
+    }
+
+    /// Домен пропал из реестра. Уже удаленные домены повторно не отмечаются.
+    fn remove(&mut self, stored: Entry) {
+        if !stored.removed {
+            self.counts.removed += 1;
+            self.removed.push(stored.url);
+        }
+    }
+
+    /// Домен есть и в реестре, и в базе данных, но колонки реестра или отметка об удалении
+    /// различаются: обновляем документ.
+    fn modify(&mut self, old: Entry, new: Entry) {
+        let mut fields = new.fields();
+
+        if old.removed {
+            self.counts.re_added += 1;
+            self.event(&new.url, RE_ADDED_EVENT, Document::new());
+            fields.insert(RE_ADDED_AT, to_datetime(self.snapshot));
+        } else {
+            self.counts.changed += 1;
+        }
+
+        // Домены, добавленные в базу до того, как стали сохраняться колонки реестра,
+        // обновляются без событий.
+        match (old.registrar, &new.registrar) {
+            (Some(registrar), Some(new_registrar)) if registrar != *new_registrar => {
+                self.event(
+                    &new.url,
+                    REGISTRAR_EVENT,
+                    doc! {"old": registrar, "new": new_registrar},
+                );
+            }
+            _ => {}
+        }
+
+        match (old.paid_till, new.paid_till) {
+            (Some(paid_till), Some(new_paid_till)) if paid_till != new_paid_till => {
+                self.event(
+                    &new.url,
+                    PAID_TILL_EVENT,
+                    doc! {"old": paid_till, "new": new_paid_till},
+                );
+            }
+            _ => {}
+        }
+
+        let update = doc! {
+            "$set": fields,
+            "$unset": {REMOVED: "", REMOVED_AT: ""},
+        };
+        self.updated.push((new.url, update));
+    }
+
+    /// Записывает накопленные изменения, если буфер заполнен, или все, если `all`.
+    fn flush(&mut self, all: bool) {
+        let summary = &mut self.summary;
+
+        if all || self.added.len() >= MONGODB_CHUNK_SIZE {
+            let added = self.added.split_off(0);
+            if let Some(ref mut target) = self.target {
+                insert_documents(&target.coll, added, &mut target.added, &mut summary.added);
+            }
+        }
+        if all || self.updated.len() >= MONGODB_UPDATE_CHUNK_SIZE {
+            let updated = self.updated.split_off(0);
+            if let Some(ref mut target) = self.target {
+                update_changed_records(
+                    &target.db,
+                    &target.coll,
+                    updated,
+                    &mut target.changed,
+                    &mut summary.changed,
+                );
+            }
+        }
+        if all || self.events.len() >= MONGODB_CHUNK_SIZE {
+            let events = self.events.split_off(0);
+            if let Some(ref mut target) = self.target {
+                insert_documents(
+                    &target.events,
+                    events,
+                    &mut target.inserted_events,
+                    &mut summary.events,
+                );
+            }
+        }
+    }
+
+    /// Записывает оставшиеся изменения. Пропавшие из реестра домены отмечаются удаленными,
+    /// только если `remove`, иначе они остаются в базе данных без событий.
+    fn finish(&mut self, remove: bool) {
+        if remove {
+            for domain in self.removed.clone() {
+                self.event(&domain, REMOVED_EVENT, Document::new());
+            }
+        }
+        self.flush(true);
+
+        if let Some(ref target) = self.target {
+            target.added.finish();
+            target.changed.finish();
+            target.inserted_events.finish();
+            if remove {
+                update_removed_records(
+                    &target.coll,
+                    &self.removed,
+                    self.snapshot,
+                    &mut self.summary.removed,
+                );
+            }
+        }
+    }
+}
+
+/// Заменяет имя домена в строке реестра канонической формой, чтобы строки сортировались
+/// в том же порядке, что и домены в базе данных. Некорректное имя оставляется как есть.
+fn canonical_line(line: String) -> String {
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+
+    match line[..end].parse::<DomainName>() {
+        Ok(domain) if domain.as_str() != &line[..end] => format!("{}{}", domain, &line[end..]),
+        _ => line,
+    }
+}
+
+type Lines = Box<dyn Iterator<Item = io::Result<String>>>;
+
+/// Строки снимка в алфавитном порядке канонических имен доменов и число непустых строк в нем.
+/// Обычно строки в файле уже отсортированы, иначе файл сортируется во внешней памяти. В обоих
+/// случаях файл предварительно читается целиком, поэтому строки подсчитываются до сравнения.
+fn registry_lines(path: &Path, sort: &ExternalSort) -> io::Result<(u64, Lines)> {
+    let open = || -> io::Result<_> {
+        let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+        Ok(reader.lines().map(|line| line.map(canonical_line)))
+    };
+    let not_empty = |line: &io::Result<String>| matches!(line, Ok(x) if !x.trim().is_empty());
+
+    let mut lines = 0;
+    if is_sorted(open()?.inspect(|x| lines += not_empty(x) as u64))? {
+        return Ok((lines, Box::new(open()?)));
+    }
+
+    warn!("{} is not sorted, sort it on disk", path.display());
+    lines = 0;
+    let sorted = sort.sort(open()?.inspect(|x| lines += not_empty(x) as u64))?;
+    Ok((lines, Box::new(sorted)))
+}
+
+/// Первая ошибка входных данных сравнения: ошибка чтения снимка или курсора базы данных либо
+/// нарушение порядка сортировки.
+///
+/// Поток, остановленный ошибкой, выглядит для сравнения закончившимся, и все оставшиеся
+/// элементы другого потока оказались бы добавленными или удаленными. Поэтому потоки сохраняют
+/// ошибку здесь, а сравнение проверяет ее перед каждым изменением.
+#[derive(Default)]
+struct Abort(RefCell<Option<Box<dyn Error + Send + Sync>>>);
+
+impl Abort {
+    /// Сохраняет ошибку, если другой еще нет.
+    fn set(&self, err: impl Into<Box<dyn Error + Send + Sync>>) {
+        self.0.borrow_mut().get_or_insert(err.into());
+    }
+
+    fn is_set(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    fn check(&self) -> BoxResult<()> {
+        match self.0.borrow_mut().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Элементы потока до первой ошибки. Ошибка сохраняется в `abort` с пояснением `context`.
+fn until_error<'a, T: 'a, E: Display + 'a>(
+    iter: impl Iterator<Item = Result<T, E>> + 'a,
+    abort: &'a Abort,
+    context: String,
+) -> impl Iterator<Item = T> + 'a {
+    iter.map_while(move |result| {
+        result
+            .map_err(|err| abort.set(format!("{}: {}", context, err)))
+            .ok()
+    })
+}
+
+/// Сравнивает документы базы данных с записями снимка, оба потока отсортированы по имени
+/// домена. Изменения записываются по ходу сравнения, поэтому оно прекращается, как только
+/// в `abort` появилась ошибка.
+fn compare<S, R>(stored: S, registry: R, abort: &Abort, changes: &mut Changes)
+where
+    S: Iterator<Item = Entry>,
+    R: Iterator<Item = Entry>,
+{
+    for change in keyed_diff(stored, registry, |x| &x.url) {
+        if abort.is_set() {
+            break;
+        }
+
+        match change {
+            Change::Added(entry) => changes.add(entry),
+            Change::Removed(entry) => changes.remove(entry),
+            Change::Modified { old, new } => changes.modify(old, new),
+        }
+        changes.flush(false);
+    }
+}
+
+/// Сравнивает снимок реестра `lines` из `total` строк с базой данных зоны. Добавленные и
+/// измененные домены записываются в базу данных по ходу сравнения, удаленные собираются в
+/// `changes.removed`. При ошибке чтения или нарушении порядка сортировки сравнение
+/// прерывается до записи следующих изменений.
+///
+/// `not_normalized` -- число документов без канонической формы имени (только при
+/// `--dry-run`). База данных сортирует их по сохраненному написанию, поэтому порядок курсора
+/// может не совпасть с порядком канонических имен; ошибка порядка тогда это объясняет.
+fn diff_database(
+    client: &Client,
+    zone: &Zone,
+    path: &Path,
+    total: u64,
+    lines: Lines,
+    not_normalized: u64,
+    changes: &mut Changes,
+) -> BoxResult<()> {
+    info!("Connect to '{}' database ...", zone.database);
+    info!("Compare snapshot {}", path.display());
+
+    let coll = client.database(&zone.database).collection(&zone.collection);
+
+    // Получаем курсор на коллекцию в базе данных. Сортируем выдачу по полю 'url' по алфавиту.
+    let options = FindOptions::builder()
+        .sort(Some(doc! {FIND_KEY: 1}))
+        .projection(Some(doc! {
+            FIND_KEY: 1,
+            UNICODE_KEY: 1,
+            REMOVED: 1,
+            "registrar": 1,
+            "created": 1,
+            "paid_till": 1,
+            "free_date": 1,
+            "delegated": 1,
+        }))
+        .build();
+    // Порядок сортировки проверяется: если он разойдется с порядком строк в Rust (например,
+    // из-за другой collation), сравнение прерывается, а не портит базу данных. Так же
+    // прерывается сравнение при ошибке курсора.
+    let abort = Abort::default();
+    let collection = format!("collection '{}'", zone.collection);
+    let cursor = until_error(coll.find(None, Some(options))?, &abort, collection.clone())
+        .filter_map(|doc| match Entry::try_from(doc) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                warn!("collection '{}' -- {}", zone.collection, err);
+                None
+            }
+        });
+    let mut stored_order = ascending(cursor, Side::Right, |x: &Entry| x.url.clone());
+    let mut stored: u64 = 0;
+    let order_context = if not_normalized > 0 {
+        format!(
+            "{} is not sorted by canonical names, because {} domain names are not normalized \
+             yet (run update without --dry-run to normalize them)",
+            collection, not_normalized
+        )
+    } else {
+        collection
+    };
+    let records = until_error(stored_order.by_ref(), &abort, order_context)
+        .inspect(|x| stored += !x.removed as u64);
+
+    // Получаем итератор на записи в файле. Некорректные строки пропускаем, ошибка чтения файла
+    // прерывает обновление.
+    let mut malformed: usize = 0;
+    let lines = RegistryReader::from_lines(lines)
+        .map_while(|result| match result {
+            Err(RegistryError::Io(err)) => {
+                abort.set(RegistryError::Io(err));
+                None
+            }
+            result => Some(result),
+        })
+        .filter_map(|result| match result {
+            Ok(record) => Some(record),
+            Err(err) => {
+                warn!("{} -- {}", path.display(), err);
+                malformed += 1;
+                None
+            }
+        });
+    let mut registry_order = ascending(lines, Side::Left, |x: &RegistryRecord| x.domain.clone());
+    let mut progress = Progress::new(format!("Compare zone '{}'", zone.name), Some(total));
+    let registry = until_error(registry_order.by_ref(), &abort, path.display().to_string())
+        .inspect(|_| progress.add(1))
+        .map(Entry::from);
+
+    compare(records, registry, &abort, changes);
+    progress.finish();
+    changes.records = progress.done();
+    changes.stored = stored;
+    let duplicates = registry_order.duplicates();
+    let stored_duplicates = stored_order.duplicates();
+    drop(registry_order);
+
+    abort.check()?;
+    if duplicates > 0 {
+        warn!("Skipped {} duplicate lines", duplicates);
+    }
+    if stored_duplicates > 0 {
+        warn!(
+            "Skipped {} duplicate documents in collection '{}'",
+            stored_duplicates, zone.collection
+        );
+    }
+    if malformed > 0 {
+        warn!("Skipped {} malformed lines", malformed);
+    }
+    changes.counts.malformed = malformed;
+    changes.flush(true);
+
+    Ok(())
+}
+
+/// Количество успешных и неудачных операций записи.
+#[derive(Debug, Default)]
+struct Writes {
+    done: u64,
+    failed: u64,
+}
+
+impl Display for Writes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.failed > 0 {
+            write!(f, "{} ({} failed)", self.done, self.failed)
+        } else {
+            write!(f, "{}", self.done)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Summary {
+    added: Writes,
+    removed: Writes,
+    changed: Writes,
+    events: Writes,
+}
+
+impl Summary {
+    fn failed(&self) -> u64 {
+        self.added.failed + self.removed.failed + self.changed.failed + self.events.failed
+    }
+}
+
+fn update_removed_records(
+    coll: &Collection,
+    domains: &[DomainName],
+    snapshot: NaiveDate,
+    writes: &mut Writes,
+) {
+    let update = doc! {"$set": {REMOVED: true, REMOVED_AT: to_datetime(snapshot)}};
+    let mut progress = Progress::new("Remove domains", Some(domains.len() as u64));
+
+    for domains in domains.chunks(MONGODB_UPDATE_CHUNK_SIZE) {
+        let names = domains.iter().map(DomainName::as_str).collect::<Vec<_>>();
+        let query = doc! {FIND_KEY: {"$in": names}};
+
+        match coll.update_many(query, update.clone(), None) {
+            Ok(result) => writes.done += result.modified_count as u64,
+            Err(err) => {
+                error!("Failed to mark {} domains removed: {}", domains.len(), err);
+                writes.failed += domains.len() as u64;
+            }
+        }
+
+        progress.add(domains.len() as u64);
+    }
+
+    progress.finish();
+}
+
+/// Обновляет документы пачками: каждая пачка отправляется одной командой `update` без
+/// соблюдения порядка, поэтому ошибка одного документа не мешает обновлению остальных. Каждая
+/// ошибка журналируется с именем домена.
+fn update_changed_records(
+    db: &Database,
+    coll: &Collection,
+    updates: Vec<(DomainName, Document)>,
+    progress: &mut Progress,
+    writes: &mut Writes,
+) {
+    for chunk in updates.chunks(MONGODB_UPDATE_CHUNK_SIZE) {
+        let count = chunk.len() as u64;
+        let statements = chunk
+            .iter()
+            .map(|(domain, update)| {
+                Bson::from(doc! {"q": {FIND_KEY: domain.as_str()}, "u": update})
+            })
+            .collect::<Vec<_>>();
+        let command = doc! {"update": coll.name(), "updates": statements, "ordered": false};
+
+        let reply = match db.run_command(command, None) {
+            Ok(reply) => reply,
+            Err(err) => {
+                error!("Failed to update {} documents: {}", count, err);
+                writes.failed += count;
+                continue;
+            }
+        };
+
+        let errors = reply
+            .get_array("writeErrors")
+            .map_or(&[][..], Vec::as_slice);
+        for error in errors.iter().filter_map(Bson::as_document) {
+            let domain = error
+                .get_i32("index")
+                .ok()
+                .and_then(|x| chunk.get(x as usize))
+                .map_or("", |(domain, _)| domain.as_str());
+            let message = error.get_str("errmsg").unwrap_or_default();
+            error!("{} -- update failed: {}", domain, message);
+        }
+        if let Ok(error) = reply.get_document("writeConcernError") {
+            warn!("Write concern error: {}", error);
+        }
+
+        writes.failed += errors.len() as u64;
+        writes.done += reply.get_i32("nModified").unwrap_or_default() as u64;
+        progress.add(count);
+    }
+}
+
+/// Вставляет документы пачками без соблюдения порядка: ошибка одного документа не мешает
+/// вставке остальных. Каждая ошибка журналируется с именем домена.
+fn insert_documents(
+    coll: &Collection,
+    docs: Vec<Document>,
+    progress: &mut Progress,
+    writes: &mut Writes,
+) {
+    let options = InsertManyOptions::builder().ordered(Some(false)).build();
+    let mut docs = docs.into_iter().peekable();
+
+    while docs.peek().is_some() {
+        let chunk = docs.by_ref().take(MONGODB_CHUNK_SIZE).collect::<Vec<_>>();
+        let count = chunk.len() as u64;
+        let domain = |index: usize| {
+            chunk[index]
+                .get_str(FIND_KEY)
+                .or_else(|_| chunk[index].get_str("domain"))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        match coll.insert_many(chunk.clone(), options.clone()) {
+            Ok(result) => writes.done += result.inserted_ids.len() as u64,
+            Err(err) => match err.kind.as_ref() {
+                ErrorKind::BulkWriteError(BulkWriteFailure {
+                    write_errors: Some(errors),
+                    ..
+                }) => {
+                    for error in errors {
+                        error!(
+                            "{} -- insert failed: {}",
+                            domain(error.index),
+                            error.message
+                        );
+                    }
+                    writes.failed += errors.len() as u64;
+                    writes.done += count - errors.len() as u64;
+                }
+                _ => {
+                    error!("Failed to insert {} documents: {}", count, err);
+                    writes.failed += count;
+                }
+            },
+        }
+
+        progress.add(count);
+    }
+}
+
+/// Приводит к канонической форме имена доменов, сохраненные до ее появления, и добавляет
+/// к ним Unicode-форму. Документы с Unicode-формой не затрагиваются.
+///
+/// Если в базе данных хранились несколько написаний одного имени (например, `ПРИМЕР.РФ` и
+/// `xn--e1afmkfd.xn--p1ai`), документ с канонической формой остается, недостающие в нем поля
+/// переносятся из дубликата, а дубликат удаляется. Ошибки записи журналируются и
+/// подсчитываются, нормализация остальных документов продолжается.
+fn normalize_documents(coll: &Collection) -> BoxResult<Writes> {
+    let filter = doc! {UNICODE_KEY: {"$exists": false}};
+    let mut writes = Writes::default();
+    let count = coll.count_documents(filter.clone(), None)?;
+    if count == 0 {
+        return Ok(writes);
+    }
+
+    let options = FindOptions::builder()
+        .projection(Some(doc! {FIND_KEY: 1}))
+        .build();
+    let mut progress = Progress::new("Normalize domain names", Some(count as u64));
+    let mut merged = 0;
+
+    for doc in coll.find(filter, Some(options))? {
+        let doc = doc?;
+        let url = doc.get_str(FIND_KEY).unwrap_or_default();
+        let id = doc.get("_id").cloned().unwrap_or(Bson::Null);
+        progress.add(1);
+
+        let domain = match url.parse::<DomainName>() {
+            Ok(domain) => domain,
+            Err(err) => {
+                warn!("{}", err);
+                continue;
+            }
+        };
+
+        let result = if domain.as_str() == url {
+            Ok(None)
+        } else {
+            let query = doc! {FIND_KEY: domain.as_str(), "_id": {"$ne": id.clone()}};
+            coll.find_one(query, None)
+        };
+        let result = result.and_then(|canonical| match canonical {
+            Some(canonical) => merge_duplicate(coll, &id, &canonical).map(|_| merged += 1),
+            None => {
+                let update =
+                    doc! {"$set": {FIND_KEY: domain.as_str(), UNICODE_KEY: domain.to_unicode()}};
+                coll.update_one(doc! {"_id": id}, update, None)
+                    .map(|_| writes.done += 1)
+            }
+        });
+
+        if let Err(err) = result {
+            error!("{} -- normalization failed: {}", url, err);
+            writes.failed += 1;
+        }
+    }
+
+    progress.finish();
+    if merged > 0 {
+        warn!("Merged {} duplicate spellings of domain names", merged);
+    }
+    if writes.failed > 0 {
+        error!("Failed to normalize {} domain names", writes.failed);
+    }
+
+    Ok(writes)
+}
+
+/// Переносит в документ `canonical` поля дубликата с идентификатором `id`, которых в нем нет,
+/// и удаляет дубликат.
+fn merge_duplicate(
+    coll: &Collection,
+    id: &Bson,
+    canonical: &Document,
+) -> mongodb::error::Result<()> {
+    let duplicate = match coll.find_one(doc! {"_id": id.clone()}, None)? {
+        Some(duplicate) => duplicate,
+        None => return Ok(()),
+    };
+    warn!(
+        "{} -- duplicate of {}, merged",
+        duplicate.get_str(FIND_KEY).unwrap_or_default(),
+        canonical.get_str(FIND_KEY).unwrap_or_default()
+    );
+
+    let missing = duplicate
+        .into_iter()
+        .filter(|(key, _)| !canonical.contains_key(key))
+        .collect::<Document>();
+    if !missing.is_empty() {
+        let canonical_id = canonical.get("_id").cloned().unwrap_or(Bson::Null);
+        coll.update_one(doc! {"_id": canonical_id}, doc! {"$set": missing}, None)?;
+    }
+    coll.delete_one(doc! {"_id": id.clone()}, None)?;
+
+    Ok(())
+}
+
+/// При `--dry-run` имена доменов не нормализуются, поэтому отчет для документов без
+/// канонической формы будет неточным. Возвращает число таких документов.
+fn warn_not_normalized(client: &Client, zone: &Zone) -> BoxResult<u64> {
+    let coll = client.database(&zone.database).collection(&zone.collection);
+    let count = coll.count_documents(doc! {UNICODE_KEY: {"$exists": false}}, None)?;
+
+    if count > 0 {
+        warn!(
+            "{} domain names in zone '{}' are not normalized yet, the report may be inaccurate",
+            count, zone.name
+        );
+    }
+
+    Ok(count as u64)
+}
+
+/// Проверяет, что снимок не пустой и не обрезан. Выполняется до сравнения, потому что
+/// добавленные и измененные домены записываются по ходу сравнения.
+fn check_lines(guard: &Guard, zone: &Zone, lines: u64) -> Result<(), String> {
+    guard
+        .check_lines(lines, zone.min_lines)
+        .map_err(|err| format!("zone '{}': update aborted: {}", zone.name, err))
+}
+
+/// Проверяет, что снимок не удаляет из базы данных слишком много доменов. Выполняется после
+/// сравнения, когда добавленные и измененные домены уже записаны, поэтому непройденная
+/// проверка отменяет только удаление.
+fn check_changes(guard: &Guard, zone: &Zone, changes: &Changes) -> Result<(), String> {
+    guard
+        .check_removed(changes.stored, changes.removed.len() as u64)
+        .map_err(|err| format!("zone '{}': {}", zone.name, err))
+}
+
+/// С `--force` и `--dry-run` непройденная проверка только журналируется, иначе возвращается
+/// ошибка.
+fn enforce(check: Result<(), String>, options: &Options) -> BoxResult<()> {
+    match check {
+        Err(err) if options.force => warn!("{} (ignored with --force)", err),
+        Err(err) if options.dry_run => warn!("{}", err),
+        Err(err) => return Err(format!("{}; use --force to apply it anyway", err).into()),
+        Ok(()) => {}
+    }
+
+    Ok(())
+}
+
+pub fn run(client: &Client, config: &Config, zones: &[&Zone], options: &Options) -> BoxResult<()> {
+    let mut failed = 0;
+    let mut skipped = vec![];
+    let mut reports = vec![];
+
+    let with_report = options.dry_run || options.report.is_some();
+
+    for zone in zones {
+        let (date, path) = find_snapshot(zone, options.snapshot)?;
+        let (lines, registry) = registry_lines(&path, &config.sort)?;
+        enforce(check_lines(&config.guard, zone, lines), options)?;
+
+        let target = if options.dry_run {
+            None
+        } else {
+            Some(Target::new(client.database(&zone.database), zone))
+        };
+        let not_normalized = match target {
+            Some(ref target) => {
+                failed += normalize_documents(&target.coll)?.failed;
+                0
+            }
+            None => warn_not_normalized(client, zone)?,
+        };
+        let mut changes = Changes::new(date, target, with_report);
+        diff_database(
+            client,
+            zone,
+            &path,
+            lines,
+            registry,
+            not_normalized,
+            &mut changes,
+        )?;
+
+        if with_report {
+            reports.push(Report::new(&zone.name, &changes));
+        }
+        let removals = enforce(check_changes(&config.guard, zone, &changes), options);
+        if !options.dry_run {
+            changes.finish(removals.is_ok());
+
+            let summary = &changes.summary;
+            info!(
+                "Zone '{}': added {}, removed {}, changed {}, events {}",
+                zone.name, summary.added, summary.removed, summary.changed, summary.events
+            );
+            failed += summary.failed();
+        }
+        if let Err(err) = removals {
+            error!("{}", err);
+            skipped.push(zone.name.as_str());
+        }
+    }
+
+    if !reports.is_empty() {
+        report::write(&reports, options.report.as_deref(), options.report_format)?;
+    }
+
+    if !skipped.is_empty() {
+        return Err(format!("domains are not removed from zones: {}", skipped.join(", ")).into());
+    }
+    if failed > 0 {
+        return Err(format!("{} database writes failed", failed).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, until_error, Abort, Changes, Entry};
+    use ::zones::{ascending, DomainName, Side};
+    use chrono::NaiveDate;
+
+    fn entry(name: &str) -> Entry {
+        Entry {
+            url: name.parse().unwrap(),
+            unicode: None,
+            removed: false,
+            registrar: None,
+            created: None,
+            paid_till: None,
+            free_date: None,
+            delegated: None,
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<DomainName> {
+        names.iter().map(|x| x.parse().unwrap()).collect()
+    }
+
+    /// Сравнивает документы `stored` со снимком `registry` так же, как `diff_database`: с
+    /// проверкой порядка обоих потоков и без базы данных.
+    fn diff(stored: &[&str], registry: &[&str]) -> (Changes, Result<(), String>) {
+        let abort = Abort::default();
+        let date = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let mut changes = Changes::new(date, None, true);
+        let side = |names: &[&str], side| {
+            let entries = names.iter().map(|x| entry(x)).collect::<Vec<_>>();
+            ascending(entries, side, |x: &Entry| x.url.clone())
+        };
+
+        compare(
+            until_error(side(stored, Side::Right), &abort, "collection".into()),
+            until_error(side(registry, Side::Left), &abort, "snapshot".into()),
+            &abort,
+            &mut changes,
+        );
+
+        let result = abort.check().map_err(|err| err.to_string());
+        (changes, result)
+    }
+
+    #[test]
+    fn compare_sorted() {
+        let (changes, result) = diff(&["a.ru", "b.ru", "d.ru"], &["a.ru", "c.ru", "d.ru"]);
+
+        assert_eq!(Ok(()), result);
+        assert_eq!(Some(names(&["c.ru"])), changes.added_domains);
+        assert_eq!(names(&["b.ru"]), changes.removed);
+    }
+
+    #[test]
+    fn descending_registry_stops_comparison() {
+        let (changes, result) = diff(&["a.ru", "b.ru", "c.ru", "d.ru"], &["a.ru", "c.ru", "b.ru"]);
+
+        assert!(result.unwrap_err().starts_with("snapshot: "));
+        // Оборванный снимок не выглядит закончившимся: d.ru не удаляется, ничего не добавляется.
+        assert_eq!(names(&["b.ru"]), changes.removed);
+        assert!(changes.added.is_empty());
+        assert!(changes.events.is_empty());
+    }
+
+    #[test]
+    fn descending_collection_stops_comparison() {
+        let (changes, result) = diff(&["a.ru", "c.ru", "b.ru"], &["a.ru", "b.ru", "c.ru", "d.ru"]);
+
+        assert!(result.unwrap_err().starts_with("collection: "));
+        // b.ru стоит в базе данных не на своем месте, поэтому до обнаружения ошибки считается
+        // добавленным, но d.ru и следующие за ним домены уже не добавляются.
+        assert_eq!(Some(names(&["b.ru"])), changes.added_domains);
+        assert_eq!(1, changes.added.len());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::archive::Retention;
+use crate::guard::Guard;
+use crate::lookup::Lookup;
+use crate::sort::ExternalSort;
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+/// Путь к файлу конфигурации по умолчанию.
+pub const DEFAULT_CONFIG: &str = "zones.toml";
+
+const DEFAULT_COLLECTION: &str = "domains";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownZone(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => {
+                write!(f, "can't read config '{}': {}", path.display(), err)
+            }
+            ConfigError::Parse(path, err) => {
+                write!(f, "can't parse config '{}': {}", path.display(), err)
+            }
+            ConfigError::UnknownZone(name) => write!(f, "zone '{}' not found in config", name),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Описание одной доменной зоны: откуда загружать список доменов, в каком каталоге хранить
+/// его снимки и в какой коллекции базы данных он хранится.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Zone {
+    pub name: String,
+    pub url: String,
+    pub archive: PathBuf,
+    pub database: String,
+    #[serde(default = "default_collection")]
+    pub collection: String,
+    /// Минимальное ожидаемое число строк в снимке зоны.
+    #[serde(default)]
+    pub min_lines: u64,
+}
+
+fn default_collection() -> String {
+    DEFAULT_COLLECTION.into()
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub retention: Retention,
+    #[serde(default)]
+    pub guard: Guard,
+    #[serde(default)]
+    pub sort: ExternalSort,
+    #[serde(default)]
+    pub lookup: Lookup,
+    #[serde(default, rename = "zone")]
+    pub zones: Vec<Zone>,
+}
+
+impl Config {
+    pub fn load<P>(path: P) -> Result<Config, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|x| ConfigError::Io(path.into(), x))?;
+
+        toml::from_str(&text).map_err(|x| ConfigError::Parse(path.into(), x))
+    }
+
+    /// Возвращает зоны с указанными именами в порядке их следования в конфигурации.
+    /// Пустой список имен означает все зоны.
+    pub fn select(&self, names: &[String]) -> Result<Vec<&Zone>, ConfigError> {
+        if let Some(name) = names
+            .iter()
+            .find(|name| !self.zones.iter().any(|zone| zone.name == **name))
+        {
+            return Err(ConfigError::UnknownZone(name.clone()));
+        }
+
+        Ok(self
+            .zones
+            .iter()
+            .filter(|zone| names.is_empty() || names.contains(&zone.name))
+            .collect())
+    }
+}
+
+// Общие для всех утилит параметры командной строки: файл конфигурации и выбор зон.
+#[derive(Debug, StructOpt)]
+pub struct ConfigOptions {
+    /// Path to the zones config file
+    #[structopt(
+        long,
+        value_name = "path",
+        default_value = DEFAULT_CONFIG,
+        parse(from_os_str),
+        global = true
+    )]
+    pub config: PathBuf,
+    /// Process only this zone (may be repeated, all zones by default)
+    #[structopt(
+        long = "zone",
+        value_name = "name",
+        number_of_values = 1,
+        global = true
+    )]
+    pub zones: Vec<String>,
+}
+
+impl ConfigOptions {
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        Config::load(&self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ConfigError};
+
+    const CONFIG: &str = r#"
+        [retention]
+        daily = 3
+
+        [guard]
+        max_removed = 500
+
+        [sort]
+        memory = 64
+
+        [lookup]
+        concurrency = 8
+        rate = 20.5
+        rescan_failed = 0
+
+        [[zone]]
+        name = "ru"
+        url = "https://ru-tld.ru/files/RU_Domains_ru-tld.ru.gz"
+        archive = "zones/ru"
+        database = "ru_zone"
+        min_lines = 1000
+
+        [[zone]]
+        name = "su"
+        url = "https://ru-tld.ru/files/SU_Domains_ru-tld.ru.gz"
+        archive = "zones/su"
+        database = "su_zone"
+        collection = "names"
+    "#;
+
+    #[test]
+    fn parse_zones() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(2, config.zones.len());
+        assert_eq!("ru", config.zones[0].name);
+        assert_eq!("ru_zone", config.zones[0].database);
+        assert_eq!("domains", config.zones[0].collection);
+        assert_eq!("names", config.zones[1].collection);
+        assert_eq!(1000, config.zones[0].min_lines);
+        assert_eq!(0, config.zones[1].min_lines);
+    }
+
+    #[test]
+    fn parse_guard() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(500, config.guard.max_removed);
+        assert_eq!(5.0, config.guard.max_removed_percent);
+    }
+
+    #[test]
+    fn parse_sort() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(64, config.sort.memory);
+        assert_eq!(None, config.sort.dir);
+    }
+
+    #[test]
+    fn parse_lookup() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(8, config.lookup.concurrency);
+        assert_eq!(20.5, config.lookup.rate);
+        assert_eq!(10, config.lookup.burst);
+        assert_eq!(256, config.lookup.batch);
+        assert_eq!(300, config.lookup.lease);
+        assert_eq!(None, config.lookup.worker);
+        assert_eq!(0, config.lookup.rescan_failed);
+        assert_eq!(30, config.lookup.rescan_succeeded);
+    }
+
+    #[test]
+    fn parse_retention() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        assert_eq!(3, config.retention.daily);
+        assert_eq!(4, config.retention.weekly);
+        assert_eq!(12, config.retention.monthly);
+    }
+
+    #[test]
+    fn select_all_zones() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        let zones = config.select(&[]).unwrap();
+
+        assert_eq!(2, zones.len());
+    }
+
+    #[test]
+    fn select_one_zone() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        let zones = config.select(&["su".to_string()]).unwrap();
+
+        assert_eq!(1, zones.len());
+        assert_eq!("su", zones[0].name);
+    }
+
+    #[test]
+    fn select_unknown_zone() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        let result = config.select(&["moscow".to_string()]);
+
+        assert!(matches!(result, Err(ConfigError::UnknownZone(name)) if name == "moscow"));
+    }
+}
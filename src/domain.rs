@@ -0,0 +1,254 @@
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Наибольшая длина метки доменного имени.
+const MAX_LABEL_LENGTH: usize = 63;
+/// Наибольшая длина доменного имени без завершающей точки.
+const MAX_LENGTH: usize = 253;
+
+/// Доменное имя в канонической форме: ASCII в нижнем регистре, интернационализированные метки
+/// записаны в punycode (A-label, `xn--`).
+///
+/// Имена сравниваются и сортируются по канонической форме, поэтому `ПРИМЕР.РФ` и
+/// `xn--e1afmkfd.xn--p1ai` — одно и то же имя. Завершающая точка полного имени (`example.ru.`)
+/// отбрасывается.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DomainName(String);
+
+impl DomainName {
+    /// Каноническая форма для DNS и HTTP.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Имя в Unicode (U-label) для показа и поиска.
+    pub fn to_unicode(&self) -> String {
+        idna::domain_to_unicode(&self.0).0
+    }
+
+    /// Метки имени слева направо.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.0.split('.')
+    }
+
+    /// Уровень имени: число меток.
+    pub fn level(&self) -> usize {
+        self.labels().count()
+    }
+
+    /// Домен верхнего уровня, например `ru` или `xn--p1ai`.
+    pub fn tld(&self) -> &str {
+        self.0.rsplit('.').next().unwrap_or_default()
+    }
+
+    /// Родительская зона имени, `None` для домена верхнего уровня.
+    pub fn parent(&self) -> Option<DomainName> {
+        self.0
+            .split_once('.')
+            .map(|(_, parent)| DomainName(parent.into()))
+    }
+
+    /// Является ли имя поддоменом (любого уровня) имени `other`.
+    pub fn is_subdomain_of(&self, other: &DomainName) -> bool {
+        self.0.len() > other.0.len()
+            && self.0.ends_with(&other.0)
+            && self.0.as_bytes()[self.0.len() - other.0.len() - 1] == b'.'
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomainError {
+    Empty,
+    TooLong(String),
+    /// Пустая метка или метка длиннее 63 символов.
+    LabelLength(String),
+    /// Метка начинается или заканчивается дефисом.
+    Hyphen(String),
+    Character(String, char),
+    /// Имя не удалось преобразовать в punycode.
+    Invalid(String),
+}
+
+impl Display for DomainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DomainError::Empty => write!(f, "empty domain name"),
+            DomainError::TooLong(value) => write!(
+                f,
+                "domain name '{}' is longer than {} characters",
+                value, MAX_LENGTH
+            ),
+            DomainError::LabelLength(value) => write!(
+                f,
+                "domain name '{}' has an empty label or a label longer than {} characters",
+                value, MAX_LABEL_LENGTH
+            ),
+            DomainError::Hyphen(value) => write!(
+                f,
+                "domain name '{}' has a label starting or ending with a hyphen",
+                value
+            ),
+            DomainError::Character(value, c) => {
+                write!(
+                    f,
+                    "domain name '{}' contains invalid character {:?}",
+                    value, c
+                )
+            }
+            DomainError::Invalid(value) => write!(f, "invalid domain name '{}'", value),
+        }
+    }
+}
+
+impl std::error::Error for DomainError {}
+
+impl FromStr for DomainName {
+    type Err = DomainError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_suffix('.').unwrap_or(s);
+        if name.is_empty() {
+            return Err(DomainError::Empty);
+        }
+
+        let ascii = idna::domain_to_ascii(name).map_err(|_| DomainError::Invalid(s.into()))?;
+
+        if let Some(c) = ascii
+            .chars()
+            .find(|&c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.'))
+        {
+            return Err(DomainError::Character(s.into(), c));
+        }
+        if ascii.len() > MAX_LENGTH {
+            return Err(DomainError::TooLong(s.into()));
+        }
+        for label in ascii.split('.') {
+            if label.is_empty() || label.len() > MAX_LABEL_LENGTH {
+                return Err(DomainError::LabelLength(s.into()));
+            }
+            if label.starts_with('-') || label.ends_with('-') {
+                return Err(DomainError::Hyphen(s.into()));
+            }
+        }
+
+        Ok(DomainName(ascii))
+    }
+}
+
+impl Display for DomainName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for DomainName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for DomainName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for DomainName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomainError, DomainName};
+
+    fn name(s: &str) -> DomainName {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn normalize_ascii() {
+        let domain = name("YANDEX.RU.");
+
+        assert_eq!("yandex.ru", domain.as_str());
+        assert_eq!("yandex.ru", domain.to_unicode());
+    }
+
+    #[test]
+    fn normalize_unicode() {
+        let domain = name("ПРИМЕР.РФ");
+
+        assert_eq!("xn--e1afmkfd.xn--p1ai", domain.as_str());
+        assert_eq!("пример.рф", domain.to_unicode());
+        assert_eq!(domain, name("xn--e1afmkfd.xn--p1ai"));
+    }
+
+    #[test]
+    fn sort_by_canonical_form() {
+        let mut domains = [name("пример.рф"), name("xn--80a.xn--p1ai"), name("ABC.РФ")];
+
+        domains.sort();
+
+        let domains = domains.iter().map(DomainName::as_str).collect::<Vec<_>>();
+        assert_eq!(
+            vec!["abc.xn--p1ai", "xn--80a.xn--p1ai", "xn--e1afmkfd.xn--p1ai"],
+            domains
+        );
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert_eq!(Err(DomainError::Empty), "".parse::<DomainName>());
+        assert_eq!(Err(DomainError::Empty), ".".parse::<DomainName>());
+        assert_eq!(
+            Err(DomainError::Character("bad_name.ru".into(), '_')),
+            "bad_name.ru".parse::<DomainName>()
+        );
+        assert_eq!(
+            Err(DomainError::LabelLength("example..ru".into())),
+            "example..ru".parse::<DomainName>()
+        );
+        assert!(matches!(
+            format!("{}.ru", "a".repeat(64)).parse::<DomainName>(),
+            Err(DomainError::LabelLength(_))
+        ));
+        assert!(matches!(
+            format!("{}.ru", "a.".repeat(127)).parse::<DomainName>(),
+            Err(DomainError::TooLong(_))
+        ));
+        assert_eq!(
+            Err(DomainError::Hyphen("-example.ru".into())),
+            "-example.ru".parse::<DomainName>()
+        );
+    }
+
+    #[test]
+    fn zone_relations() {
+        let domain = name("www.example.ru");
+
+        assert_eq!(3, domain.level());
+        assert_eq!("ru", domain.tld());
+        assert_eq!(Some(name("example.ru")), domain.parent());
+        assert_eq!(None, name("ru").parent());
+        assert!(domain.is_subdomain_of(&name("example.ru")));
+        assert!(domain.is_subdomain_of(&name("ru")));
+        assert!(!domain.is_subdomain_of(&domain));
+        assert!(!name("myexample.ru").is_subdomain_of(&name("example.ru")));
+    }
+
+    #[test]
+    fn serde_as_string() {
+        let domain: DomainName = serde_json::from_str("\"ПРИМЕР.РФ\"").unwrap();
+
+        assert_eq!(
+            "\"xn--e1afmkfd.xn--p1ai\"",
+            serde_json::to_string(&domain).unwrap()
+        );
+        assert!(serde_json::from_str::<DomainName>("\"bad name\"").is_err());
+    }
+}
@@ -0,0 +1,669 @@
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{
+    HeaderMap, CONTENT_RANGE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED,
+    RANGE,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const ATTEMPTS: usize = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Http(reqwest::Error),
+    Io(io::Error),
+    Status(StatusCode),
+    /// Загруженный файл не является корректным gzip архивом.
+    Corrupt(io::Error),
+    /// Сервер ответил на запрос докачки не с того места, с которого она запрошена.
+    UnexpectedRange {
+        expected: u64,
+        range: String,
+    },
+    /// Все попытки загрузки завершились обрывом соединения.
+    Interrupted(usize),
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Http(err) => write!(f, "http error: {}", err),
+            DownloadError::Io(err) => write!(f, "io error: {}", err),
+            DownloadError::Status(status) => write!(f, "unexpected response status {}", status),
+            DownloadError::Corrupt(err) => write!(f, "downloaded file is corrupt: {}", err),
+            DownloadError::UnexpectedRange { expected, range } => write!(
+                f,
+                "server sent range '{}' instead of bytes {}-",
+                range, expected
+            ),
+            DownloadError::Interrupted(attempts) => {
+                write!(f, "download interrupted {} times, giving up", attempts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(err: reqwest::Error) -> Self {
+        DownloadError::Http(err)
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(err: io::Error) -> Self {
+        DownloadError::Io(err)
+    }
+}
+
+/// Результат загрузки файла.
+#[derive(Debug, PartialEq)]
+pub enum Download {
+    /// Файл на сервере не изменился с прошлой загрузки.
+    NotModified,
+    /// Файл загружен, указан его размер в байтах и заголовки ответа для следующей загрузки.
+    Downloaded { size: u64, validators: Validators },
+}
+
+/// Заголовки ETag и Last-Modified, по которым сервер определяет, изменился ли файл.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(String::from)
+        };
+
+        Validators {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    name.into()
+}
+
+/// Загрузчик файлов зон.
+///
+/// Файл сначала пишется во временный `<file>.part`, а заголовки ответа — в `<file>.part.toml`.
+/// При обрыве соединения, в том числе в прошлом запуске, загрузка продолжается с места остановки
+/// через заголовки Range и If-Range. Готовый файл проверяется распаковкой gzip потока и только
+/// после этого заменяет предыдущую версию. Если сервер сообщает, что файл не изменился, загрузка
+/// не выполняется.
+pub struct Downloader {
+    client: Client,
+    attempts: usize,
+    retry_delay: Duration,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Downloader::with_retries(ATTEMPTS, RETRY_DELAY)
+    }
+}
+
+impl Downloader {
+    pub fn with_retries(attempts: usize, retry_delay: Duration) -> Self {
+        Downloader {
+            client: Client::new(),
+            attempts,
+            retry_delay,
+        }
+    }
+
+    /// Загружает файл во временный, проверяет и переименовывает его в `path`. Заголовки `known`
+    /// предыдущей загрузки позволяют пропустить неизменившийся файл.
+    pub fn download(
+        &self,
+        url: &str,
+        path: &Path,
+        known: &Validators,
+    ) -> Result<Download, DownloadError> {
+        let part = with_suffix(path, ".part");
+        let state = with_suffix(path, ".part.toml");
+        let (mut current, mut received) = match load_partial(&part, &state) {
+            Some((validators, size)) => (Some(validators), size),
+            None => (None, 0),
+        };
+        let mut failures = 0;
+
+        loop {
+            let mut request = self.client.get(url);
+
+            match current {
+                Some(ref current) if received > 0 => {
+                    info!("Resume download {} from {} bytes ...", url, received);
+                    request = request.header(RANGE, format!("bytes={}-", received));
+                    if let Some(validator) =
+                        current.etag.as_ref().or(current.last_modified.as_ref())
+                    {
+                        request = request.header(IF_RANGE, validator.as_str());
+                    }
+                }
+                _ => {
+                    info!("Start download {} ... ", url);
+                    if let Some(ref etag) = known.etag {
+                        request = request.header(IF_NONE_MATCH, etag.as_str());
+                    }
+                    if let Some(ref last_modified) = known.last_modified {
+                        request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+                    }
+                }
+            }
+
+            let result = request
+                .send()
+                .map_err(DownloadError::from)
+                .and_then(|response| {
+                    self.receive(response, &part, &state, &mut current, &mut received)
+                });
+
+            match result {
+                Ok(true) => break,
+                Ok(false) => {
+                    info!("{} not modified", url);
+                    discard(&part, &state);
+                    return Ok(Download::NotModified);
+                }
+                Err(err @ DownloadError::Http(_))
+                | Err(err @ DownloadError::Io(_))
+                | Err(err @ DownloadError::UnexpectedRange { .. }) => {
+                    warn!("Download {} interrupted: {}", url, err);
+                    failures += 1;
+                    if failures == self.attempts {
+                        return Err(DownloadError::Interrupted(failures));
+                    }
+                    thread::sleep(self.retry_delay);
+                }
+                Err(err) => {
+                    discard(&part, &state);
+                    return Err(err);
+                }
+            }
+        }
+
+        if let Err(err) = verify_gzip(&part) {
+            discard(&part, &state);
+            return Err(DownloadError::Corrupt(err));
+        }
+
+        fs::rename(&part, path)?;
+        let _ = fs::remove_file(&state);
+        info!("Download {} bytes", received);
+
+        Ok(Download::Downloaded {
+            size: received,
+            validators: current.unwrap_or_default(),
+        })
+    }
+
+    /// Обрабатывает один ответ сервера. `Ok(false)` означает, что файл не изменился.
+    fn receive(
+        &self,
+        mut response: Response,
+        part: &Path,
+        state: &Path,
+        current: &mut Option<Validators>,
+        received: &mut u64,
+    ) -> Result<bool, DownloadError> {
+        let file = match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(false),
+            StatusCode::PARTIAL_CONTENT if *received > 0 => {
+                let range = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|x| x.to_str().ok())
+                    .unwrap_or_default();
+
+                // Если сервер прислал не тот диапазон, дописывать его нельзя: следующая попытка
+                // загружает файл заново.
+                if range_start(range) != Some(*received) {
+                    let err = DownloadError::UnexpectedRange {
+                        expected: *received,
+                        range: range.to_string(),
+                    };
+                    *current = None;
+                    *received = 0;
+                    return Err(err);
+                }
+
+                OpenOptions::new().append(true).open(part)?
+            }
+            StatusCode::OK => {
+                // Сервер не поддерживает докачку или файл изменился: начинаем заново.
+                let validators = Validators::from_headers(response.headers());
+                save_validators(state, &validators)?;
+                *current = Some(validators);
+                *received = 0;
+
+                response
+                    .content_length()
+                    .map_or_else(|| {}, |x| info!("Content {} bytes length.", x));
+
+                File::create(part)?
+            }
+            status => return Err(DownloadError::Status(status)),
+        };
+
+        let mut writer = BufWriter::new(file);
+        let result = io::copy(&mut response, &mut writer);
+        writer.flush()?;
+
+        match result {
+            Ok(bytes) => {
+                *received += bytes;
+                Ok(true)
+            }
+            Err(err) => {
+                *received = fs::metadata(part)?.len();
+                // Без валидаторов нельзя убедиться, что докачивается тот же самый файл.
+                if current.as_ref().map_or(true, Validators::is_empty) {
+                    *received = 0;
+                }
+                Err(DownloadError::Io(err))
+            }
+        }
+    }
+}
+
+/// Начало диапазона из заголовка Content-Range вида `bytes 100-199/200`.
+fn range_start(range: &str) -> Option<u64> {
+    let (start, _) = range.trim().strip_prefix("bytes ")?.split_once('-')?;
+
+    start.trim().parse().ok()
+}
+
+fn save_validators(state: &Path, validators: &Validators) -> io::Result<()> {
+    let text =
+        toml::to_string(validators).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    fs::write(state, text)
+}
+
+/// Заголовки и размер недокачанного файла, оставшегося от прерванного запуска. Без заголовков
+/// нельзя убедиться, что на сервере тот же файл, поэтому такой файл загружается заново.
+fn load_partial(part: &Path, state: &Path) -> Option<(Validators, u64)> {
+    let validators: Validators = toml::from_str(&fs::read_to_string(state).ok()?).ok()?;
+    let size = fs::metadata(part).ok()?.len();
+
+    Some((validators, size)).filter(|(validators, size)| !validators.is_empty() && *size > 0)
+}
+
+fn discard(part: &Path, state: &Path) {
+    let _ = fs::remove_file(part);
+    let _ = fs::remove_file(state);
+}
+
+/// Распаковывает файл целиком. Файл может состоять из нескольких gzip потоков подряд.
+fn verify_gzip(path: &Path) -> io::Result<()> {
+    let mut decoder = MultiGzDecoder::new(BufReader::new(File::open(path)?));
+
+    io::copy(&mut decoder, &mut io::sink()).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Download, DownloadError, Downloader, Validators};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    type Headers = HashMap<String, String>;
+
+    /// Простейший HTTP сервер: на каждое соединение вызывает обработчик с заголовками запроса
+    /// и отправляет клиенту возвращенные им байты.
+    fn serve<F>(handler: F) -> (String, Arc<Mutex<Vec<Headers>>>)
+    where
+        F: Fn(usize, &Headers) -> Vec<u8> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/zone.gz", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let log = requests.clone();
+
+        thread::spawn(move || {
+            for (n, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = Headers::new();
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(pos) = line.find(':') {
+                        headers.insert(
+                            line[..pos].to_lowercase(),
+                            line[pos + 1..].trim().to_string(),
+                        );
+                    }
+                }
+
+                let response = handler(n, &headers);
+                log.lock().unwrap().push(headers);
+                let _ = stream.write_all(&response);
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn response(status: &str, headers: &[(&str, String)], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("\r\n");
+
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn target(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("zones-download-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("zone.gz")
+    }
+
+    fn downloader() -> Downloader {
+        Downloader::with_retries(3, Duration::from_millis(0))
+    }
+
+    #[test]
+    fn download_with_validators() {
+        let body = gzip("example.ru\n");
+        let served = body.clone();
+        let (url, _) = serve(move |_, _| {
+            response(
+                "200 OK",
+                &[
+                    ("Content-Length", served.len().to_string()),
+                    ("ETag", "\"v1\"".into()),
+                ],
+                &served,
+            )
+        });
+        let path = target("fresh");
+
+        let result = downloader()
+            .download(&url, &path, &Validators::default())
+            .unwrap();
+
+        let validators = Validators {
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+        };
+        assert_eq!(
+            Download::Downloaded {
+                size: body.len() as u64,
+                validators
+            },
+            result
+        );
+        assert_eq!(body, fs::read(&path).unwrap());
+    }
+
+    #[test]
+    fn skip_not_modified() {
+        let (url, requests) = serve(|_, headers| {
+            if headers.get("if-none-match").map(String::as_str) == Some("\"v1\"") {
+                response("304 Not Modified", &[], b"")
+            } else {
+                response(
+                    "500 Internal Server Error",
+                    &[("Content-Length", "0".into())],
+                    b"",
+                )
+            }
+        });
+        let path = target("not-modified");
+        fs::write(&path, gzip("old.ru\n")).unwrap();
+        let validators = Validators {
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+        };
+
+        let result = downloader().download(&url, &path, &validators).unwrap();
+
+        assert_eq!(Download::NotModified, result);
+        assert_eq!(gzip("old.ru\n"), fs::read(&path).unwrap());
+        assert_eq!(1, requests.lock().unwrap().len());
+    }
+
+    #[test]
+    fn resume_interrupted_download() {
+        let body = gzip(&"example.ru\n".repeat(1000));
+        let served = body.clone();
+        let half = body.len() / 2;
+        let (url, requests) = serve(move |n, headers| {
+            let length = ("Content-Length", served.len().to_string());
+            let etag = ("ETag", "\"v2\"".to_string());
+            match n {
+                // Обрываем соединение на середине файла.
+                0 => response("200 OK", &[length, etag], &served[..half]),
+                _ => {
+                    let range = headers["range"]
+                        .trim_start_matches("bytes=")
+                        .trim_end_matches('-');
+                    let from: usize = range.parse().unwrap();
+                    response(
+                        "206 Partial Content",
+                        &[
+                            ("Content-Length", (served.len() - from).to_string()),
+                            (
+                                "Content-Range",
+                                format!("bytes {}-{}/{}", from, served.len() - 1, served.len()),
+                            ),
+                            etag,
+                        ],
+                        &served[from..],
+                    )
+                }
+            }
+        });
+        let path = target("resume");
+
+        let result = downloader()
+            .download(&url, &path, &Validators::default())
+            .unwrap();
+
+        assert!(matches!(result, Download::Downloaded { size, .. } if size == body.len() as u64));
+        assert_eq!(body, fs::read(&path).unwrap());
+        let requests = requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert_eq!(format!("bytes={}-", half), requests[1]["range"]);
+        assert_eq!("\"v2\"", requests[1]["if-range"]);
+    }
+
+    /// Сервер, отдающий `body` целиком или с запрошенного места.
+    fn ranged(body: Vec<u8>) -> impl Fn(usize, &Headers) -> Vec<u8> + Send + 'static {
+        move |_, headers| {
+            let etag = ("ETag", "\"v2\"".to_string());
+            let from = match headers.get("range") {
+                Some(range) => range
+                    .trim_start_matches("bytes=")
+                    .trim_end_matches('-')
+                    .parse::<usize>()
+                    .unwrap(),
+                None => {
+                    let length = ("Content-Length", body.len().to_string());
+                    return response("200 OK", &[length, etag], &body);
+                }
+            };
+
+            response(
+                "206 Partial Content",
+                &[
+                    ("Content-Length", (body.len() - from).to_string()),
+                    (
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", from, body.len() - 1, body.len()),
+                    ),
+                    etag,
+                ],
+                &body[from..],
+            )
+        }
+    }
+
+    #[test]
+    fn resume_after_restart() {
+        let body = gzip(&"example.ru\n".repeat(1000));
+        let half = body.len() / 2;
+        let (url, requests) = serve(ranged(body.clone()));
+        let path = target("restart");
+        // Прошлый запуск упал на середине файла.
+        fs::write(path.with_extension("gz.part"), &body[..half]).unwrap();
+        fs::write(path.with_extension("gz.part.toml"), "etag = '\"v2\"'\n").unwrap();
+
+        let result = downloader()
+            .download(&url, &path, &Validators::default())
+            .unwrap();
+
+        assert!(matches!(result, Download::Downloaded { size, .. } if size == body.len() as u64));
+        assert_eq!(body, fs::read(&path).unwrap());
+        assert!(!path.with_extension("gz.part.toml").exists());
+        let requests = requests.lock().unwrap();
+        assert_eq!(1, requests.len());
+        assert_eq!(format!("bytes={}-", half), requests[0]["range"]);
+        assert_eq!("\"v2\"", requests[0]["if-range"]);
+    }
+
+    #[test]
+    fn restart_on_unexpected_range() {
+        let body = gzip(&"example.ru\n".repeat(1000));
+        let served = body.clone();
+        let half = body.len() / 2;
+        let (url, requests) = serve(move |n, headers| match n {
+            0 => response(
+                "200 OK",
+                &[
+                    ("Content-Length", served.len().to_string()),
+                    ("ETag", "\"v2\"".into()),
+                ],
+                &served[..half],
+            ),
+            // Сервер игнорирует запрошенное место и отдает файл с начала.
+            1 => response(
+                "206 Partial Content",
+                &[
+                    ("Content-Length", served.len().to_string()),
+                    (
+                        "Content-Range",
+                        format!("bytes 0-{}/{}", served.len() - 1, served.len()),
+                    ),
+                ],
+                &served,
+            ),
+            _ => ranged(served.clone())(n, headers),
+        });
+        let path = target("range");
+
+        let result = downloader()
+            .download(&url, &path, &Validators::default())
+            .unwrap();
+
+        assert!(matches!(result, Download::Downloaded { size, .. } if size == body.len() as u64));
+        assert_eq!(body, fs::read(&path).unwrap());
+        let requests = requests.lock().unwrap();
+        assert_eq!(3, requests.len());
+        assert_eq!(format!("bytes={}-", half), requests[1]["range"]);
+        assert!(!requests[2].contains_key("range"));
+    }
+
+    #[test]
+    fn verify_all_gzip_members() {
+        let mut body = gzip("a.ru\n");
+        body.extend_from_slice(&gzip("b.ru\n"));
+        let valid = body.clone();
+        let (url, _) = serve(move |_, _| {
+            response(
+                "200 OK",
+                &[("Content-Length", valid.len().to_string())],
+                &valid,
+            )
+        });
+
+        let path = target("members");
+        let result = downloader().download(&url, &path, &Validators::default());
+        assert!(result.is_ok());
+
+        // Второй поток обрезан.
+        body.truncate(body.len() - 4);
+        let (url, _) = serve(move |_, _| {
+            response(
+                "200 OK",
+                &[("Content-Length", body.len().to_string())],
+                &body,
+            )
+        });
+
+        let path = target("truncated-member");
+        let result = downloader().download(&url, &path, &Validators::default());
+        assert!(matches!(result, Err(DownloadError::Corrupt(_))));
+    }
+
+    #[test]
+    fn keep_old_file_when_corrupt() {
+        let (url, _) = serve(|_, _| {
+            let body = b"not a gzip stream";
+            response(
+                "200 OK",
+                &[("Content-Length", body.len().to_string())],
+                body,
+            )
+        });
+        let path = target("corrupt");
+        fs::write(&path, gzip("old.ru\n")).unwrap();
+
+        let result = downloader().download(&url, &path, &Validators::default());
+
+        assert!(matches!(result, Err(DownloadError::Corrupt(_))));
+        assert_eq!(gzip("old.ru\n"), fs::read(&path).unwrap());
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// Причина, по которой не удалось получить страницу сайта.
+///
+/// В базе данных хранится в поле `reason` вместе с полями варианта, например
+/// `{reason: "http_status", status: 403, message: "..."}`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum FailureReason {
+    /// Имя не разрешается в адрес.
+    Dns,
+    /// Сервер отклонил соединение.
+    Refused,
+    Timeout,
+    /// Ошибка TLS, в том числе недействительный или просроченный сертификат.
+    Tls,
+    /// Сервер ответил кодом, отличным от 200. Код знаковый, потому что BSON не поддерживает
+    /// беззнаковые числа.
+    HttpStatus {
+        status: i32,
+    },
+    /// Не удалось прочитать или декодировать тело ответа.
+    Decode,
+    /// Тело ответа больше допустимого размера.
+    TooLarge,
+    /// Перенаправление на уже посещенный адрес.
+    RedirectLoop,
+    /// Перенаправлений больше допустимого числа.
+    TooManyRedirects,
+    /// Прочие ошибки соединения.
+    Other,
+}
+
+impl Display for FailureReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureReason::Dns => write!(f, "dns error"),
+            FailureReason::Refused => write!(f, "connection refused"),
+            FailureReason::Timeout => write!(f, "time out"),
+            FailureReason::Tls => write!(f, "tls error"),
+            FailureReason::HttpStatus { status } => write!(f, "http {}", status),
+            FailureReason::Decode => write!(f, "decode error"),
+            FailureReason::TooLarge => write!(f, "response too large"),
+            FailureReason::RedirectLoop => write!(f, "redirect loop"),
+            FailureReason::TooManyRedirects => write!(f, "too many redirects"),
+            FailureReason::Other => write!(f, "connection error"),
+        }
+    }
+}
+
+/// Неудачная попытка получить страницу сайта по одному протоколу.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Failure {
+    #[serde(flatten)]
+    pub reason: FailureReason,
+    /// Текст ошибки со всеми ее причинами.
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(reason: FailureReason, message: impl Into<String>) -> Self {
+        Failure {
+            reason,
+            message: message.into(),
+        }
+    }
+
+    /// Определяет причину по цепочке ошибок. `timeout` — признак истекшего времени ожидания,
+    /// который HTTP клиент сообщает отдельно от цепочки.
+    pub fn from_error(err: &(dyn Error + 'static), timeout: bool) -> Self {
+        let message = chain(err);
+        let text = message.to_lowercase();
+
+        let io_kind = sources(err)
+            .filter_map(|x| x.downcast_ref::<io::Error>())
+            .map(io::Error::kind)
+            .next();
+
+        let reason = if timeout || io_kind == Some(io::ErrorKind::TimedOut) {
+            FailureReason::Timeout
+        } else if text.contains("dns error") || text.contains("failed to lookup address") {
+            FailureReason::Dns
+        } else if io_kind == Some(io::ErrorKind::ConnectionRefused)
+            || text.contains("connection refused")
+        {
+            FailureReason::Refused
+        } else if ["certificate", "ssl", "tls", "handshake"]
+            .iter()
+            .any(|x| text.contains(x))
+        {
+            FailureReason::Tls
+        } else {
+            FailureReason::Other
+        };
+
+        Failure { reason, message }
+    }
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_body() || err.is_decode() {
+            let reason = if err.is_timeout() {
+                FailureReason::Timeout
+            } else {
+                FailureReason::Decode
+            };
+            return Failure::new(reason, chain(&err));
+        }
+
+        Failure::from_error(&err, err.is_timeout())
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.reason, self.message)
+    }
+}
+
+fn sources<'a>(err: &'a (dyn Error + 'static)) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(Some(err), |&x| x.source())
+}
+
+/// Текст ошибки и всех ее причин через двоеточие.
+fn chain(err: &(dyn Error + 'static)) -> String {
+    sources(err)
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Failure, FailureReason};
+    use std::error::Error;
+    use std::fmt::{self, Display, Formatter};
+    use std::io;
+
+    /// Ошибка-обертка, как ее возвращает HTTP клиент.
+    #[derive(Debug)]
+    struct Connect(io::Error);
+
+    impl Display for Connect {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "error trying to connect")
+        }
+    }
+
+    impl Error for Connect {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    fn reason(err: io::Error) -> FailureReason {
+        Failure::from_error(&Connect(err), false).reason
+    }
+
+    #[test]
+    fn classify_errors() {
+        let other = |msg: &str| io::Error::new(io::ErrorKind::Other, msg);
+
+        assert_eq!(
+            FailureReason::Refused,
+            reason(io::ErrorKind::ConnectionRefused.into())
+        );
+        assert_eq!(
+            FailureReason::Timeout,
+            reason(io::ErrorKind::TimedOut.into())
+        );
+        assert_eq!(
+            FailureReason::Dns,
+            reason(other("dns error: failed to lookup address information"))
+        );
+        assert_eq!(
+            FailureReason::Tls,
+            reason(other("certificate verify failed (certificate has expired)"))
+        );
+        assert_eq!(FailureReason::Other, reason(other("connection reset")));
+        assert_eq!(
+            FailureReason::Timeout,
+            Failure::from_error(&Connect(other("operation timed out")), true).reason
+        );
+    }
+
+    #[test]
+    fn message_with_sources() {
+        let failure = Failure::from_error(&Connect(io::ErrorKind::ConnectionRefused.into()), false);
+
+        assert_eq!(
+            "error trying to connect: connection refused",
+            failure.message
+        );
+    }
+
+    #[test]
+    fn serde_with_reason_tag() {
+        let failure = Failure::new(FailureReason::HttpStatus { status: 403 }, "Forbidden");
+
+        let value = serde_json::to_value(&failure).unwrap();
+
+        assert_eq!("http_status", value["reason"]);
+        assert_eq!(403, value["status"]);
+        assert_eq!("Forbidden", value["message"]);
+        assert_eq!(failure, serde_json::from_value(value).unwrap());
+
+        let doc = bson::to_document(&failure).unwrap();
+        assert_eq!(Ok(403), doc.get_i32("status"));
+        assert_eq!(failure, bson::from_document(doc).unwrap());
+
+        let timeout = serde_json::json!({"reason": "timeout", "message": "time out"});
+        assert_eq!(
+            FailureReason::Timeout,
+            serde_json::from_value::<Failure>(timeout).unwrap().reason
+        );
+    }
+}
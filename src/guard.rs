@@ -0,0 +1,153 @@
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+
+/// Ограничения, защищающие базу данных от массового удаления доменов, если файл зоны оказался
+/// пустым или обрезанным. При превышении любого из порогов домены зоны не удаляются.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Guard {
+    /// Наибольшее число доменов, удаляемых за одно обновление.
+    #[serde(default = "Guard::default_max_removed")]
+    pub max_removed: u64,
+    /// Наибольшая доля удаляемых доменов в процентах от доменов зоны в базе данных.
+    #[serde(default = "Guard::default_max_removed_percent")]
+    pub max_removed_percent: f64,
+}
+
+impl Guard {
+    fn default_max_removed() -> u64 {
+        100_000
+    }
+
+    fn default_max_removed_percent() -> f64 {
+        5.0
+    }
+
+    /// Проверяет, что в снимке не меньше `min_lines` строк. Эту проверку можно выполнить до
+    /// сравнения снимка с базой данных.
+    pub fn check_lines(&self, lines: u64, min_lines: u64) -> Result<(), GuardError> {
+        if lines < min_lines {
+            return Err(GuardError::TooFewLines {
+                lines,
+                min: min_lines,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Проверяет, что из `stored` неудаленных доменов зоны в базе данных пропавших из снимка
+    /// `removed` не слишком много.
+    pub fn check_removed(&self, stored: u64, removed: u64) -> Result<(), GuardError> {
+        let percent = if stored > 0 {
+            removed as f64 * 100.0 / stored as f64
+        } else {
+            0.0
+        };
+        if removed > self.max_removed || percent > self.max_removed_percent {
+            return Err(GuardError::TooManyRemoved {
+                removed,
+                stored,
+                percent,
+                max: self.max_removed,
+                max_percent: self.max_removed_percent,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Guard {
+    fn default() -> Self {
+        Guard {
+            max_removed: Guard::default_max_removed(),
+            max_removed_percent: Guard::default_max_removed_percent(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GuardError {
+    TooFewLines {
+        lines: u64,
+        min: u64,
+    },
+    TooManyRemoved {
+        removed: u64,
+        stored: u64,
+        percent: f64,
+        max: u64,
+        max_percent: f64,
+    },
+}
+
+impl Display for GuardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardError::TooFewLines { lines, min } => write!(
+                f,
+                "snapshot has only {} lines, at least {} expected; the file may be empty or truncated",
+                lines, min
+            ),
+            GuardError::TooManyRemoved {
+                removed,
+                stored,
+                percent,
+                max,
+                max_percent,
+            } => write!(
+                f,
+                "{} of {} domains ({:.2}%) would be removed, limit is {} domains or {}%; \
+                 the snapshot may be truncated",
+                removed, stored, percent, max, max_percent
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Guard, GuardError};
+
+    fn guard() -> Guard {
+        Guard {
+            max_removed: 1000,
+            max_removed_percent: 5.0,
+        }
+    }
+
+    #[test]
+    fn allow_regular_update() {
+        assert_eq!(Ok(()), guard().check_lines(10_000, 5_000));
+        assert_eq!(Ok(()), guard().check_removed(10_000, 100));
+        assert_eq!(Ok(()), guard().check_lines(0, 0));
+        assert_eq!(Ok(()), guard().check_removed(0, 0));
+    }
+
+    #[test]
+    fn reject_short_snapshot() {
+        assert_eq!(
+            Err(GuardError::TooFewLines {
+                lines: 10,
+                min: 5_000
+            }),
+            guard().check_lines(10, 5_000)
+        );
+    }
+
+    #[test]
+    fn reject_mass_removal() {
+        // Превышен процент.
+        assert!(matches!(
+            guard().check_removed(10_000, 600),
+            Err(GuardError::TooManyRemoved { removed: 600, .. })
+        ));
+        // Превышено абсолютное число.
+        assert!(matches!(
+            guard().check_removed(1_000_000, 1001),
+            Err(GuardError::TooManyRemoved { removed: 1001, .. })
+        ));
+    }
+}
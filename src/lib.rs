@@ -1,7 +1,39 @@
+mod archive;
+mod charset;
+mod config;
+mod domain;
+mod download;
+mod failure;
+mod guard;
+mod lookup;
+mod media_type;
+mod progress;
+mod redirect;
+mod registry;
+mod sort;
 mod tools;
 mod parsers;
 
-pub use tools::symmetric_diff;
+pub use archive::{Archive, Retention, Snapshot};
+pub use charset::{decode_html, CharsetSource, Decoded};
+pub use config::{Config, ConfigError, ConfigOptions, Zone, DEFAULT_CONFIG};
+pub use domain::{DomainError, DomainName};
+pub use download::{Download, DownloadError, Downloader, Validators};
+pub use failure::{Failure, FailureReason};
+pub use guard::{Guard, GuardError};
+pub use lookup::{Lookup, RateLimiter};
+pub use media_type::MediaType;
+pub use progress::Progress;
+pub use redirect::{is_cross_domain, Redirect, RedirectKind};
+pub use registry::{RecordError, RegistryError, RegistryReader, RegistryRecord};
+pub use sort::{is_sorted, ExternalSort, Sorted};
+pub use tools::{
+    ascending, diff, diff_by, keyed_diff, symmetric_diff, symmetric_diff_checked, Ascending,
+    Change, Diff, DiffIter, OrderError, Side,
+};
 pub use parsers::Site;
 pub use parsers::parse_title;
 pub use parsers::parse_metadata;
+pub use parsers::parse_redirect;
+pub use parsers::OpenGraph;
+pub use parsers::field_name;
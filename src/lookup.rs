@@ -0,0 +1,246 @@
+use serde::Deserialize;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Параметры сбора метаинформации с сайтов.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Lookup {
+    /// Сколько доменов одной зоны проверяется одновременно.
+    #[serde(default = "Lookup::default_concurrency")]
+    pub concurrency: usize,
+    /// Наибольшее число HTTP запросов в секунду для всех зон вместе, 0 — без ограничения.
+    #[serde(default = "Lookup::default_rate")]
+    pub rate: f64,
+    /// Сколько запросов можно отправить подряд без задержки.
+    #[serde(default = "Lookup::default_burst")]
+    pub burst: u32,
+    /// Сколько документов курсор получает из базы данных за один раз.
+    #[serde(default = "Lookup::default_batch")]
+    pub batch: u32,
+    /// Сколько результатов может ждать записи в базу данных. Когда очередь заполнена, новые
+    /// домены не проверяются, пока запись не догонит.
+    #[serde(default = "Lookup::default_writes")]
+    pub writes: usize,
+    /// Период вывода статистики в журнал в секундах.
+    #[serde(default = "Lookup::default_report")]
+    pub report: u64,
+    /// На сколько секунд процесс захватывает домен для проверки. Если процесс упал, по истечении
+    /// аренды домен проверит другой процесс.
+    #[serde(default = "Lookup::default_lease")]
+    pub lease: u64,
+    /// Имя процесса в аренде, по умолчанию `<имя хоста>:<pid>`.
+    pub worker: Option<String>,
+    /// Наибольшее число перенаправлений при запросе стартовой страницы.
+    #[serde(default = "Lookup::default_redirects")]
+    pub redirects: usize,
+    /// Сколько из них может быть перенаправлениями meta refresh и скриптами, 0 — не проходить
+    /// такие перенаправления.
+    #[serde(default = "Lookup::default_html_redirects")]
+    pub html_redirects: usize,
+    /// Наибольший размер страницы в мегабайтах, страницы больше не загружаются.
+    #[serde(default = "Lookup::default_max_body")]
+    pub max_body: usize,
+    /// Через сколько дней проверять заново домены без веб сервера, 0 — не проверять.
+    #[serde(default = "Lookup::default_rescan_failed")]
+    pub rescan_failed: u64,
+    /// Через сколько дней проверять заново домены с веб сервером, 0 — не проверять.
+    #[serde(default = "Lookup::default_rescan_succeeded")]
+    pub rescan_succeeded: u64,
+}
+
+impl Lookup {
+    fn default_concurrency() -> usize {
+        32
+    }
+
+    fn default_rate() -> f64 {
+        50.0
+    }
+
+    fn default_burst() -> u32 {
+        10
+    }
+
+    fn default_batch() -> u32 {
+        256
+    }
+
+    fn default_writes() -> usize {
+        1024
+    }
+
+    fn default_report() -> u64 {
+        60
+    }
+
+    fn default_lease() -> u64 {
+        300
+    }
+
+    fn default_redirects() -> usize {
+        10
+    }
+
+    fn default_html_redirects() -> usize {
+        3
+    }
+
+    fn default_max_body() -> usize {
+        4
+    }
+
+    fn default_rescan_failed() -> u64 {
+        7
+    }
+
+    fn default_rescan_succeeded() -> u64 {
+        30
+    }
+
+    /// Имя процесса, которым помечаются захваченные им домены.
+    pub fn worker_id(&self) -> String {
+        if let Some(ref worker) = self.worker {
+            return worker.clone();
+        }
+
+        let host = std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| fs::read_to_string("/etc/hostname").ok())
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .unwrap_or_else(|| "localhost".into());
+
+        format!("{}:{}", host, std::process::id())
+    }
+
+    pub fn limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.rate, self.burst)
+    }
+}
+
+impl Default for Lookup {
+    fn default() -> Self {
+        Lookup {
+            concurrency: Lookup::default_concurrency(),
+            rate: Lookup::default_rate(),
+            burst: Lookup::default_burst(),
+            batch: Lookup::default_batch(),
+            writes: Lookup::default_writes(),
+            report: Lookup::default_report(),
+            lease: Lookup::default_lease(),
+            worker: None,
+            redirects: Lookup::default_redirects(),
+            html_redirects: Lookup::default_html_redirects(),
+            max_body: Lookup::default_max_body(),
+            rescan_failed: Lookup::default_rescan_failed(),
+            rescan_succeeded: Lookup::default_rescan_succeeded(),
+        }
+    }
+}
+
+/// Ограничение частоты запросов по алгоритму «ведро с токенами».
+///
+/// Ведро вмещает `burst` токенов и пополняется со скоростью `rate` токенов в секунду, каждый
+/// запрос забирает один токен. Ограничитель можно разделять между задачами через `Arc`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// Нулевая или отрицательная частота отключает ограничение.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+
+        RateLimiter {
+            rate,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Ждет, пока в ведре появится токен, и забирает его.
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire(Instant::now()) {
+            tokio::time::delay_for(wait).await;
+        }
+    }
+
+    /// Забирает токен или возвращает время, через которое он появится.
+    fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        if self.rate <= 0.0 {
+            return Ok(());
+        }
+
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn spend_burst_then_wait() {
+        let limiter = RateLimiter::new(10.0, 3);
+        let now = Instant::now();
+
+        assert_eq!(Ok(()), limiter.try_acquire(now));
+        assert_eq!(Ok(()), limiter.try_acquire(now));
+        assert_eq!(Ok(()), limiter.try_acquire(now));
+        let wait = limiter.try_acquire(now).unwrap_err();
+        assert!((wait.as_secs_f64() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn refill_over_time() {
+        let limiter = RateLimiter::new(10.0, 2);
+        let now = Instant::now();
+
+        assert_eq!(Ok(()), limiter.try_acquire(now));
+        assert_eq!(Ok(()), limiter.try_acquire(now));
+
+        // За 150 мс набирается полтора токена: один запрос проходит, до следующего 50 мс.
+        let later = now + Duration::from_millis(150);
+        assert_eq!(Ok(()), limiter.try_acquire(later));
+        let wait = limiter.try_acquire(later).unwrap_err();
+        assert!((wait.as_secs_f64() - 0.05).abs() < 1e-9);
+
+        // Долгий простой не копит токенов больше, чем вмещает ведро.
+        let idle = later + Duration::from_secs(60);
+        assert_eq!(Ok(()), limiter.try_acquire(idle));
+        assert_eq!(Ok(()), limiter.try_acquire(idle));
+        assert!(limiter.try_acquire(idle).is_err());
+    }
+
+    #[test]
+    fn unlimited() {
+        let limiter = RateLimiter::new(0.0, 1);
+        let now = Instant::now();
+
+        assert!((0..100).all(|_| limiter.try_acquire(now).is_ok()));
+    }
+}
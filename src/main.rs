@@ -0,0 +1,97 @@
+mod commands;
+
+use ::zones::ConfigOptions;
+use commands::BoxResult;
+use mongodb::sync::Client;
+use structopt::StructOpt;
+
+/// Tools for scanning RU, SU and РФ zones for web servers
+#[derive(Debug, StructOpt)]
+#[structopt(name = "zones")]
+struct Options {
+    #[structopt(flatten)]
+    config: ConfigOptions,
+    /// MongoDB connection string
+    #[structopt(
+        long,
+        value_name = "uri",
+        env = "MONGODB_URI",
+        hide_env_values = true,
+        global = true
+    )]
+    mongodb_uri: Option<String>,
+    /// Log filter in env_logger format, e.g. "info" or "zones=debug"
+    #[structopt(
+        long,
+        value_name = "level",
+        env = "RUST_LOG",
+        default_value = "info",
+        global = true
+    )]
+    log_level: String,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Download zone files from the registry
+    Download,
+    /// Add new domains to the database and mark removed ones
+    Update(commands::update::Options),
+    /// Resolve domains of the zone files via root DNS servers
+    Resolve,
+    /// Collect metadata from start pages of unchecked and stale domains
+    Lookup(commands::lookup::Options),
+    /// Show domain counts per zone
+    Stats,
+    /// Export domains from the database
+    Export(commands::export::Options),
+}
+
+impl Options {
+    fn connect(&self) -> BoxResult<Client> {
+        let uri = self
+            .mongodb_uri
+            .as_ref()
+            .ok_or("You must set MONGODB_URI environment variable or --mongodb-uri option")?;
+
+        Ok(Client::with_uri_str(uri)?)
+    }
+}
+
+fn main() -> BoxResult<()> {
+    let options = Options::from_args();
+
+    pretty_env_logger::formatted_timed_builder()
+        .parse_filters(&options.log_level)
+        .init();
+
+    let config = options.config.load()?;
+    let zones = config.select(&options.config.zones)?;
+
+    match options.command {
+        Command::Download => commands::download::run(&config.retention, &zones),
+        Command::Update(ref update) => {
+            commands::update::run(&options.connect()?, &config, &zones, update)
+        }
+        Command::Resolve => commands::resolve::run(&zones),
+        Command::Lookup(ref lookup) => {
+            let client = options.connect()?;
+            let mut runtime = tokio::runtime::Builder::new()
+                .threaded_scheduler()
+                .core_threads(4)
+                .enable_all()
+                .build()?;
+
+            runtime.block_on(commands::lookup::run(
+                client,
+                &config.lookup,
+                &zones,
+                lookup,
+            ))
+        }
+        Command::Stats => commands::stats::run(&options.connect()?, &zones),
+        Command::Export(ref export) => commands::export::run(&options.connect()?, &zones, export),
+    }
+}
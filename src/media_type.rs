@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+/// Тип содержимого из заголовка Content-Type или `<meta http-equiv="Content-Type">`, например
+/// `text/html; charset=windows-1251`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    /// Тип и подтип в нижнем регистре, например `text/html`.
+    pub essence: String,
+    /// Параметры с именами в нижнем регистре. Из повторяющихся параметров берется первый.
+    pub params: BTreeMap<String, String>,
+}
+
+impl MediaType {
+    /// Разбирает значение по RFC 7231: параметры могут идти в любом порядке, их имена не
+    /// зависят от регистра, значения могут быть в двойных кавычках с экранированием `\`.
+    /// Параметры без значения пропускаются. Возвращает `None`, если нет типа и подтипа.
+    pub fn parse(value: &str) -> Option<Self> {
+        let end = value.find(';').unwrap_or(value.len());
+        let essence = value[..end].trim().to_ascii_lowercase();
+        let (kind, subtype) = essence.split_once('/')?;
+        if !is_token(kind) || !is_token(subtype) {
+            return None;
+        }
+
+        let mut params = BTreeMap::new();
+        let mut rest = &value[end..];
+        while let Some(param) = rest.strip_prefix(';') {
+            let end = param.find([';', '=']).unwrap_or(param.len());
+            let name = param[..end].trim().to_ascii_lowercase();
+
+            rest = &param[end..];
+            if let Some(value) = rest.strip_prefix('=') {
+                let (value, next) = parse_value(value.trim_start());
+                rest = next;
+                if !name.is_empty() && !value.is_empty() {
+                    params.entry(name).or_insert(value);
+                }
+            }
+        }
+
+        Some(MediaType { essence, params })
+    }
+
+    /// Значение параметра `charset`. Одинарные кавычки стандартом не предусмотрены, но
+    /// встречаются на сайтах, поэтому тоже убираются.
+    pub fn charset(&self) -> Option<&str> {
+        self.params
+            .get("charset")
+            .map(|x| x.trim_matches('\'').trim())
+            .filter(|x| !x.is_empty())
+    }
+}
+
+/// Значение параметра и остаток строки, начинающийся с `;` или пустой.
+fn parse_value(value: &str) -> (String, &str) {
+    let quoted = match value.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let end = value.find(';').unwrap_or(value.len());
+            return (value[..end].trim().to_string(), &value[end..]);
+        }
+    };
+
+    let mut unquoted = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next().map(|(_, c)| c)),
+            '"' => {
+                // Все после закрывающей кавычки до следующего параметра отбрасывается.
+                let rest = &quoted[pos + 1..];
+                let end = rest.find(';').unwrap_or(rest.len());
+                return (unquoted, &rest[end..]);
+            }
+            c => unquoted.push(c),
+        }
+    }
+
+    (unquoted, "")
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MediaType;
+
+    fn charset(value: &str) -> Option<String> {
+        MediaType::parse(value)?.charset().map(str::to_string)
+    }
+
+    #[test]
+    fn parse_params() {
+        let media = MediaType::parse("Text/HTML; Charset=UTF-8; q=0.9").unwrap();
+
+        assert_eq!("text/html", media.essence);
+        assert_eq!("UTF-8", media.params["charset"]);
+        assert_eq!("0.9", media.params["q"]);
+        assert_eq!(Some("UTF-8"), media.charset());
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            Some("utf-8".into()),
+            charset(r#"text/html; charset="utf-8""#)
+        );
+        assert_eq!(
+            Some("windows-1251".into()),
+            charset("text/html; charset='windows-1251'")
+        );
+        assert_eq!(
+            r#"a;b "c""#,
+            MediaType::parse(r#"text/plain; x="a;b \"c\"" junk; y=1"#)
+                .unwrap()
+                .params["x"]
+        );
+        assert_eq!(
+            Some("koi8-r".into()),
+            charset(r#"text/html; x="a;b"; charset=koi8-r"#)
+        );
+    }
+
+    #[test]
+    fn case_and_spaces() {
+        assert_eq!(
+            Some("KOI8-R".into()),
+            charset("TEXT/HTML ;CHARSET = KOI8-R ")
+        );
+        assert_eq!(Some("utf-8".into()), charset("text/html;charset=utf-8"));
+    }
+
+    #[test]
+    fn parameter_order() {
+        assert_eq!(
+            Some("utf-8".into()),
+            charset("text/html; format=flowed; charset=utf-8")
+        );
+        assert_eq!(
+            Some("utf-8".into()),
+            charset("text/html; charset=utf-8; charset=koi8-r")
+        );
+        assert_eq!(
+            Some("cp1251".into()),
+            charset("text/html; flag; charset=; charset=cp1251")
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(None, MediaType::parse("charset=utf-8"));
+        assert_eq!(None, MediaType::parse("text/; charset=utf-8"));
+        assert_eq!(None, MediaType::parse(""));
+        assert_eq!(None, charset("text/html"));
+        assert_eq!(None, charset("text/html; charset=''"));
+    }
+}
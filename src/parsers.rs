@@ -1,35 +1,115 @@
+use crate::charset::CharsetSource;
+use crate::media_type::MediaType;
+use crate::redirect::RedirectKind;
+use reqwest::Url;
 use scraper::Html;
 use scraper::Selector;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Перенаправлением считаются только короткие скрипты, в длинных присваивание адреса обычно
+/// зависит от условий.
+const MAX_REDIRECT_SCRIPT: usize = 512;
+/// Перенаправлением считается meta refresh с задержкой не больше этой, в секундах. С большей
+/// задержкой страница обычно просто периодически обновляет себя.
+const MAX_REFRESH_DELAY: f64 = 5.0;
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Site {
     pub url: String,
+    /// Код ответа сервера.
+    #[serde(default)]
+    pub status: Option<i32>,
+    /// Заголовки ответа, значения повторяющихся заголовков перечислены через запятую.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
     pub title: Option<String>,
+    /// Кодировка страницы. `parse_metadata` берет ее из `<meta charset>`, при загрузке страницы
+    /// записывается кодировка, в которой страница на самом деле декодирована.
     pub charset: Option<String>,
+    /// Откуда взята кодировка при загрузке страницы.
+    #[serde(default)]
+    pub charset_source: Option<CharsetSource>,
     pub description: Option<String>,
     pub keywords: Option<String>,
+    /// Значение `<meta http-equiv="refresh">` как есть, например `0; url=/index.html`.
+    #[serde(default)]
+    pub refresh: Option<String>,
+    /// Язык из `<meta http-equiv="Content-Language">`.
+    #[serde(default)]
+    pub content_language: Option<String>,
+    /// Режим совместимости из `<meta http-equiv="X-UA-Compatible">`.
+    #[serde(default)]
+    pub x_ua_compatible: Option<String>,
+    /// Разметка Open Graph, если на странице есть хотя бы один тег `og:`.
+    #[serde(default)]
+    pub og: Option<OpenGraph>,
+    /// Теги Twitter Card без префикса `twitter:`, например `card`, `site`, `image`.
+    #[serde(default)]
+    pub twitter: BTreeMap<String, String>,
+    /// Канонический адрес страницы из `<link rel="canonical">`.
+    #[serde(default)]
+    pub canonical: Option<String>,
+    /// Адрес значка из `<link rel="icon">`.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Язык из `<html lang>`.
+    #[serde(default)]
+    pub lang: Option<String>,
+}
+
+/// Разметка Open Graph. Из повторяющихся тегов, например нескольких `og:image`, берется первый.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct OpenGraph {
+    pub title: Option<String>,
+    pub site_name: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
 }
 
 pub fn parse_title(doc: &Html) -> Option<String> {
     let tag = Selector::parse("title").unwrap();
 
     doc.select(&tag)
-        .map(|x| {
-            x.text().collect::<String>()
-        })
+        .map(|x| x.text().collect::<String>())
         .find(|s| !s.is_empty())
 }
 
-pub fn parse_metadata(doc: &Html) -> Site {
+/// Метаинформация страницы. Относительные адреса в `og:image`, `twitter:image`, `canonical` и
+/// `icon` разрешаются относительно `<base href>` и адреса страницы `url`, после всех
+/// перенаправлений.
+pub fn parse_metadata(doc: &Html, url: Option<&Url>) -> Site {
     let meta = Selector::parse("meta").unwrap();
+    let link = Selector::parse("link[rel][href]").unwrap();
     let mut metadata = Site::default();
+    let mut http_equiv_charset = None;
+    let base = base_url(doc, url);
 
     for tag in doc.select(&meta) {
         if let Some(charset) = tag.value().attr("charset") {
             metadata.charset = Some(charset.to_lowercase());
         }
 
+        if let Some(equiv) = tag.value().attr("http-equiv") {
+            if let Some(content) = tag.value().attr("content") {
+                let content = content.trim();
+
+                match equiv.trim().to_ascii_lowercase().as_str() {
+                    "content-type" => {
+                        http_equiv_charset = http_equiv_charset.or_else(|| {
+                            MediaType::parse(content)?.charset().map(str::to_lowercase)
+                        });
+                    }
+                    "refresh" => metadata.refresh = Some(content.into()),
+                    "content-language" => metadata.content_language = Some(content.into()),
+                    "x-ua-compatible" => metadata.x_ua_compatible = Some(content.into()),
+                    _ => {}
+                }
+            }
+        }
+
         if let Some(name) = tag.value().attr("name") {
             if let Some(content) = tag.value().attr("content") {
                 if name.eq_ignore_ascii_case("description") {
@@ -41,15 +121,218 @@ pub fn parse_metadata(doc: &Html) -> Site {
                 }
             }
         }
+
+        // Open Graph размечается атрибутом property, но многие сайты пишут name, и наоборот.
+        let property = tag
+            .value()
+            .attr("property")
+            .or_else(|| tag.value().attr("name"));
+        if let Some(property) = property {
+            if let Some(content) = tag.value().attr("content") {
+                social_tag(&mut metadata, property, content, base.as_ref());
+            }
+        }
     }
 
+    for tag in doc.select(&link) {
+        let rel = tag.value().attr("rel").unwrap().to_ascii_lowercase();
+        let href = tag.value().attr("href").unwrap().trim();
+        if href.is_empty() {
+            continue;
+        }
+
+        for token in rel.split_ascii_whitespace() {
+            if token == "canonical" && metadata.canonical.is_none() {
+                metadata.canonical = Some(resolve(base.as_ref(), href));
+            }
+
+            if token == "icon" && metadata.icon.is_none() {
+                metadata.icon = Some(resolve(base.as_ref(), href));
+            }
+        }
+    }
+
+    metadata.lang = doc
+        .root_element()
+        .value()
+        .attr("lang")
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::to_string);
+
+    // `<meta charset>` важнее объявления через http-equiv.
+    metadata.charset = metadata.charset.or(http_equiv_charset);
     metadata
 }
 
+/// Тег Open Graph или Twitter Card.
+fn social_tag(metadata: &mut Site, property: &str, content: &str, base: Option<&Url>) {
+    let property = property.trim().to_ascii_lowercase();
+    let content = content.trim();
+    if content.is_empty() {
+        return;
+    }
+
+    if let Some(key) = property.strip_prefix("og:") {
+        let og = metadata.og.get_or_insert_with(OpenGraph::default);
+        let (field, value) = match key {
+            "title" => (&mut og.title, content.into()),
+            "site_name" => (&mut og.site_name, content.into()),
+            "description" => (&mut og.description, content.into()),
+            "image" | "image:url" => (&mut og.image, resolve(base, content)),
+            "type" => (&mut og.kind, content.into()),
+            _ => return,
+        };
+        field.get_or_insert(value);
+    } else if let Some(key) = property.strip_prefix("twitter:") {
+        let value = match key {
+            "image" | "image:src" => resolve(base, content),
+            _ => content.into(),
+        };
+        metadata.twitter.entry(field_name(key)).or_insert(value);
+    }
+}
+
+/// Адрес, относительно которого разрешаются ссылки страницы: `<base href>` или адрес самой
+/// страницы.
+fn base_url(doc: &Html, url: Option<&Url>) -> Option<Url> {
+    let base = Selector::parse("base[href]").unwrap();
+    let href = doc
+        .select(&base)
+        .next()
+        .map(|x| x.value().attr("href").unwrap().trim());
+
+    match (url, href) {
+        (Some(url), Some(href)) => url.join(href).ok().or_else(|| Some(url.clone())),
+        (None, Some(href)) => Url::parse(href).ok(),
+        (url, None) => url.cloned(),
+    }
+}
+
+/// Абсолютный адрес ссылки. Если адрес страницы неизвестен, ссылка остается как есть.
+fn resolve(base: Option<&Url>, href: &str) -> String {
+    base.and_then(|x| x.join(href).ok())
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| href.into())
+}
+
+/// Имя, пригодное для поля документа MongoDB: точки и `$` в начале заменяются на `_`.
+pub fn field_name(name: &str) -> String {
+    // Точка в имени поля документа MongoDB означает вложенный документ, а `$` в начале --
+    // оператор.
+    let name = name.replace('.', "_");
+    match name.strip_prefix('$') {
+        Some(rest) => format!("_{}", rest),
+        None => name,
+    }
+}
+
+/// Адрес перехода из `<meta http-equiv="refresh">` или из короткого скрипта, присваивающего
+/// `location`. Адрес возвращается как есть, относительный адрес не разрешается.
+pub fn parse_redirect(doc: &Html) -> Option<(RedirectKind, String)> {
+    let meta = Selector::parse("meta[http-equiv]").unwrap();
+    let script = Selector::parse("script").unwrap();
+
+    let refresh = doc
+        .select(&meta)
+        .filter(|x| {
+            x.value()
+                .attr("http-equiv")
+                .unwrap()
+                .eq_ignore_ascii_case("refresh")
+        })
+        .filter_map(|x| x.value().attr("content"))
+        .find_map(refresh_target);
+    if let Some(url) = refresh {
+        return Some((RedirectKind::Refresh, url));
+    }
+
+    doc.select(&script)
+        .map(|x| x.text().collect::<String>())
+        .filter(|x| x.len() <= MAX_REDIRECT_SCRIPT)
+        .find_map(|x| script_target(&x))
+        .map(|url| (RedirectKind::Script, url))
+}
+
+/// Адрес из значения meta refresh вида `5; url=http://example.ru/`. Обновление с долгой или
+/// некорректной задержкой перенаправлением не считается.
+fn refresh_target(content: &str) -> Option<String> {
+    let (delay, rest) = content.split_once([';', ','])?;
+    let delay = delay.trim().parse::<f64>().ok()?;
+    if !(0.0..=MAX_REFRESH_DELAY).contains(&delay) {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let url = match rest.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url") => {
+            rest[3..].trim_start().strip_prefix('=')?.trim()
+        }
+        _ => rest.trim(),
+    };
+
+    unquote(url)
+}
+
+/// Адрес из присваивания `location = "..."`, `location.href = "..."` или вызова
+/// `location.replace("...")`. Адреса в переменных не поддерживаются.
+fn script_target(script: &str) -> Option<String> {
+    let mut rest = script;
+
+    while let Some(pos) = rest.find("location") {
+        let identifier = rest[..pos]
+            .chars()
+            .last()
+            .map_or(false, |c| c.is_alphanumeric() || c == '_' || c == '$');
+        rest = &rest[pos + "location".len()..];
+        if identifier {
+            continue;
+        }
+
+        let value = rest.strip_prefix(".href").unwrap_or(rest).trim_start();
+        let value = if let Some(value) = value.strip_prefix('=') {
+            Some(value).filter(|x| !x.starts_with('='))
+        } else {
+            value
+                .strip_prefix(".replace(")
+                .or_else(|| value.strip_prefix(".assign("))
+        };
+
+        let url = value.and_then(|x| {
+            let x = x.trim_start();
+            let quote = x.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            let end = x[1..].find(quote)?;
+
+            unquote(&x[..end + 2])
+        });
+        if url.is_some() {
+            return url;
+        }
+    }
+
+    None
+}
+
+/// Убирает кавычки вокруг адреса, пустой адрес отбрасывается.
+fn unquote(url: &str) -> Option<String> {
+    let url = url.trim().trim_matches(|c| c == '"' || c == '\'').trim();
+
+    Some(url.to_string()).filter(|x| !x.is_empty())
+}
+
 #[cfg(test)]
 mod test {
+    use super::{field_name, parse_metadata, parse_redirect, parse_title, OpenGraph};
+    use crate::redirect::RedirectKind;
+    use reqwest::Url;
     use scraper::Html;
-    use super::{parse_title, parse_metadata};
+
+    #[test]
+    fn mongodb_field_name() {
+        assert_eq!("x_powered_by", field_name("x_powered_by"));
+        assert_eq!("image_alt", field_name("image.alt"));
+        assert_eq!("_ref", field_name("$ref"));
+    }
 
     #[test]
     fn title_exists() {
@@ -110,7 +393,7 @@ mod test {
         "#;
         let doc = Html::parse_document(html);
 
-        let metadata = parse_metadata(&doc);
+        let metadata = parse_metadata(&doc, None);
 
         assert_eq!(None, metadata.charset);
         assert_eq!(None, metadata.description);
@@ -133,10 +416,193 @@ mod test {
         "#;
         let doc = Html::parse_document(html);
 
-        let metadata = parse_metadata(&doc);
+        let metadata = parse_metadata(&doc, None);
 
         assert_eq!(Some(String::from("utf-8")), metadata.charset);
         assert_eq!(Some(String::from("description")), metadata.description);
         assert_eq!(Some(String::from("keywords")), metadata.keywords);
     }
+
+    #[test]
+    fn http_equiv() {
+        let html = r#"
+        <html>
+            <head>
+                <meta http-equiv="Content-Type" content="text/html; Charset=Windows-1251">
+                <meta http-equiv="refresh" content="30">
+                <meta http-equiv="content-language" content="ru">
+                <meta http-equiv="X-UA-Compatible" content="IE=edge">
+            </head>
+            <body/>
+        </html>
+        "#;
+        let doc = Html::parse_document(html);
+
+        let metadata = parse_metadata(&doc, None);
+
+        assert_eq!(Some(String::from("windows-1251")), metadata.charset);
+        assert_eq!(Some(String::from("30")), metadata.refresh);
+        assert_eq!(Some(String::from("ru")), metadata.content_language);
+        assert_eq!(Some(String::from("IE=edge")), metadata.x_ua_compatible);
+    }
+
+    #[test]
+    fn meta_charset_over_http_equiv() {
+        let html = r#"
+        <html>
+            <head>
+                <meta http-equiv="content-type" content='text/html; charset="koi8-r"'>
+                <meta charset="UTF-8">
+            </head>
+            <body/>
+        </html>
+        "#;
+        let doc = Html::parse_document(html);
+
+        let metadata = parse_metadata(&doc, None);
+
+        assert_eq!(Some(String::from("utf-8")), metadata.charset);
+    }
+
+    #[test]
+    fn social_tags() {
+        let html = r#"
+        <html lang="ru">
+            <head>
+                <meta property="og:title" content="Бренд">
+                <meta property="OG:site_name" content="brand.ru">
+                <meta name="og:description" content="Описание">
+                <meta property="og:image" content="/img/logo.png">
+                <meta property="og:image" content="/img/other.png">
+                <meta property="og:type" content="website">
+                <meta name="twitter:card" content="summary">
+                <meta property="twitter:site" content="@brand">
+                <meta name="twitter:image" content="card.png">
+                <link rel="canonical" href="https://brand.ru/">
+                <link rel="shortcut icon" href="favicon.ico">
+            </head>
+            <body/>
+        </html>
+        "#;
+        let doc = Html::parse_document(html);
+        let url = Url::parse("https://www.brand.ru/ru/index.html").unwrap();
+
+        let metadata = parse_metadata(&doc, Some(&url));
+
+        let og = OpenGraph {
+            title: Some("Бренд".into()),
+            site_name: Some("brand.ru".into()),
+            description: Some("Описание".into()),
+            image: Some("https://www.brand.ru/img/logo.png".into()),
+            kind: Some("website".into()),
+        };
+        assert_eq!(Some(og), metadata.og);
+        assert_eq!("summary", metadata.twitter["card"]);
+        assert_eq!("@brand", metadata.twitter["site"]);
+        assert_eq!(
+            "https://www.brand.ru/ru/card.png",
+            metadata.twitter["image"]
+        );
+        assert_eq!(Some(String::from("https://brand.ru/")), metadata.canonical);
+        assert_eq!(
+            Some(String::from("https://www.brand.ru/ru/favicon.ico")),
+            metadata.icon
+        );
+        assert_eq!(Some(String::from("ru")), metadata.lang);
+    }
+
+    #[test]
+    fn relative_to_base() {
+        let html = r#"
+        <html>
+            <head>
+                <base href="/static/">
+                <link rel="icon" href="favicon.png">
+                <link rel="canonical" href="/page">
+            </head>
+            <body/>
+        </html>
+        "#;
+        let doc = Html::parse_document(html);
+        let url = Url::parse("http://example.ru/a/b").unwrap();
+
+        let metadata = parse_metadata(&doc, Some(&url));
+
+        assert_eq!(
+            Some(String::from("http://example.ru/static/favicon.png")),
+            metadata.icon
+        );
+        assert_eq!(
+            Some(String::from("http://example.ru/page")),
+            metadata.canonical
+        );
+        assert_eq!(None, metadata.og);
+        assert_eq!(None, metadata.lang);
+
+        // Без адреса страницы ссылки остаются как есть.
+        let metadata = parse_metadata(&doc, None);
+        assert_eq!(Some(String::from("favicon.png")), metadata.icon);
+    }
+
+    fn redirect(html: &str) -> Option<(RedirectKind, String)> {
+        parse_redirect(&Html::parse_document(html))
+    }
+
+    #[test]
+    fn meta_refresh() {
+        assert_eq!(
+            Some((RedirectKind::Refresh, String::from("http://example.ru/"))),
+            redirect(r#"<meta http-equiv="Refresh" content="0;URL='http://example.ru/'">"#)
+        );
+        assert_eq!(
+            Some((RedirectKind::Refresh, String::from("/new"))),
+            redirect(r#"<meta http-equiv="refresh" content="5; url = /new">"#)
+        );
+        assert_eq!(
+            Some((RedirectKind::Refresh, String::from("index.html"))),
+            redirect(r#"<meta http-equiv="refresh" content="0, index.html">"#)
+        );
+        // Обновление страницы без адреса не перенаправляет.
+        assert_eq!(
+            None,
+            redirect(r#"<meta http-equiv="refresh" content="300">"#)
+        );
+    }
+
+    #[test]
+    fn long_refresh_delay() {
+        assert_eq!(
+            Some((RedirectKind::Refresh, String::from("/"))),
+            redirect(r#"<meta http-equiv="refresh" content="5; url=/">"#)
+        );
+        // Периодическое обновление страницы — не перенаправление.
+        assert_eq!(
+            None,
+            redirect(r#"<meta http-equiv="refresh" content="300; url=/">"#)
+        );
+        assert_eq!(
+            None,
+            redirect(r#"<meta http-equiv="refresh" content="soon; url=/">"#)
+        );
+    }
+
+    #[test]
+    fn script_redirect() {
+        let script = |code: &str| redirect(&format!("<script>{}</script>", code));
+        let target = Some((RedirectKind::Script, String::from("https://brand.ru/")));
+
+        assert_eq!(target, script(r#"window.location = "https://brand.ru/";"#));
+        assert_eq!(target, script("window.location.href='https://brand.ru/'"));
+        assert_eq!(target, script(r#"location.replace("https://brand.ru/")"#));
+        assert_eq!(
+            target,
+            script(r#"document.location.assign( 'https://brand.ru/' );"#)
+        );
+        assert_eq!(None, script("window.location = url;"));
+        assert_eq!(
+            None,
+            script(r#"if (window.location == "https://brand.ru/") {}"#)
+        );
+        assert_eq!(None, script(r#"mylocation = "https://brand.ru/";"#));
+    }
 }
@@ -0,0 +1,125 @@
+use log::info;
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Журналирует ход длительной операции: сколько сделано, скорость и оставшееся время.
+///
+/// Сообщение выводится не чаще раза в пять секунд, поэтому `add` можно вызывать на каждый
+/// обработанный элемент.
+pub struct Progress {
+    label: String,
+    total: Option<u64>,
+    done: u64,
+    started: Instant,
+    reported: Instant,
+}
+
+impl Progress {
+    pub fn new<S: Into<String>>(label: S, total: Option<u64>) -> Self {
+        let now = Instant::now();
+
+        Progress {
+            label: label.into(),
+            total,
+            done: 0,
+            started: now,
+            reported: now,
+        }
+    }
+
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    pub fn add(&mut self, count: u64) {
+        self.done += count;
+
+        if self.reported.elapsed() >= REPORT_INTERVAL {
+            self.reported = Instant::now();
+            info!("{}", self.message());
+        }
+    }
+
+    /// Выводит итоговое сообщение.
+    pub fn finish(&self) {
+        info!("{}", self.message());
+    }
+
+    fn message(&self) -> String {
+        let elapsed = self.started.elapsed();
+        let rate = rate(self.done, elapsed);
+
+        match self.total {
+            Some(total) if total > 0 => format!(
+                "{}: {}/{} ({}%), {:.0}/s, ETA {}",
+                self.label,
+                self.done,
+                total,
+                self.done * 100 / total,
+                rate,
+                eta(total.saturating_sub(self.done), rate)
+                    .map_or_else(|| "--:--".to_string(), format_duration),
+            ),
+            _ => format!("{}: {}, {:.0}/s", self.label, self.done, rate),
+        }
+    }
+}
+
+fn rate(done: u64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+
+    if seconds > 0.0 {
+        done as f64 / seconds
+    } else {
+        0.0
+    }
+}
+
+fn eta(left: u64, rate: f64) -> Option<Duration> {
+    if rate > 0.0 {
+        Some(Duration::from_secs_f64(left as f64 / rate))
+    } else {
+        None
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{eta, format_duration, rate};
+    use std::time::Duration;
+
+    #[test]
+    fn rate_per_second() {
+        assert_eq!(50.0, rate(100, Duration::from_secs(2)));
+        assert_eq!(0.0, rate(100, Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn eta_from_rate() {
+        assert_eq!(Some(Duration::from_secs(30)), eta(300, 10.0));
+        assert_eq!(None, eta(300, 0.0));
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!("00:59", format_duration(Duration::from_secs(59)));
+        assert_eq!("12:05", format_duration(Duration::from_secs(725)));
+        assert_eq!("2:00:01", format_duration(Duration::from_secs(7201)));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::domain::DomainName;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+/// Способ перенаправления.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectKind {
+    /// Ответ 3xx с заголовком Location.
+    Http,
+    /// `<meta http-equiv="refresh" content="0;url=...">`.
+    Refresh,
+    /// Присваивание `window.location` в скрипте страницы.
+    Script,
+}
+
+impl Default for RedirectKind {
+    fn default() -> Self {
+        RedirectKind::Http
+    }
+}
+
+/// Один шаг перенаправления: запрошенный адрес, ответ сервера и адрес перехода.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Redirect {
+    pub url: String,
+    pub status: i32,
+    /// Адрес перехода как есть, без разрешения относительного адреса: заголовок Location,
+    /// адрес из meta refresh или из скрипта.
+    pub location: Option<String>,
+    /// Время запроса в миллисекундах.
+    pub time: i64,
+    #[serde(default)]
+    pub kind: RedirectKind,
+}
+
+/// Уводит ли адрес с домена `domain`. Переход на сам домен, его поддомен (например, `www`) или
+/// родительский домен не считается уходом, переход на IP адрес считается.
+pub fn is_cross_domain(domain: &DomainName, url: &Url) -> bool {
+    let host = match url.host_str().and_then(|x| x.parse::<DomainName>().ok()) {
+        Some(host) => host,
+        None => return true,
+    };
+
+    !(host == *domain || host.is_subdomain_of(domain) || domain.is_subdomain_of(&host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_cross_domain;
+    use crate::domain::DomainName;
+    use reqwest::Url;
+
+    fn cross(domain: &str, url: &str) -> bool {
+        let domain = domain.parse::<DomainName>().unwrap();
+
+        is_cross_domain(&domain, &Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn same_domain() {
+        assert!(!cross("example.ru", "https://example.ru/"));
+        assert!(!cross("example.ru", "https://www.example.ru/index.html"));
+        assert!(!cross("www.example.ru", "http://example.ru"));
+        assert!(!cross("пример.рф", "https://www.xn--e1afmkfd.xn--p1ai/"));
+    }
+
+    #[test]
+    fn other_domain() {
+        assert!(cross("example.ru", "https://brand.ru/"));
+        assert!(cross("example.ru", "https://example.ru.parking.com/"));
+        assert!(cross("example.ru", "http://192.168.0.1/"));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::domain::{DomainError, DomainName};
+use chrono::NaiveDate;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+const DATE_FORMAT: &str = "%d.%m.%Y";
+
+/// Запись файла реестра ru-tld.ru.
+///
+/// Строка файла состоит из колонок, разделенных пробельными символами:
+/// `домен регистратор дата_регистрации оплачен_до [дата_освобождения] делегирован`.
+/// Даты записаны в формате `ДД.ММ.ГГГГ`, признак делегирования — `1` или `0`. Имя домена
+/// приводится к канонической форме.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryRecord {
+    pub domain: DomainName,
+    pub registrar: String,
+    pub created: NaiveDate,
+    pub paid_till: NaiveDate,
+    pub free_date: Option<NaiveDate>,
+    pub delegated: bool,
+}
+
+/// Причина, по которой строку реестра не удалось разобрать.
+#[derive(Debug, PartialEq)]
+pub enum RecordError {
+    Columns(usize),
+    Domain(DomainError),
+    Date(String),
+    Delegated(String),
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Columns(count) => write!(f, "expected 5 or 6 columns, found {}", count),
+            RecordError::Domain(err) => write!(f, "{}", err),
+            RecordError::Date(value) => write!(f, "invalid date '{}'", value),
+            RecordError::Delegated(value) => write!(f, "invalid delegation flag '{}'", value),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+fn parse_date(value: &str) -> Result<NaiveDate, RecordError> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT).map_err(|_| RecordError::Date(value.into()))
+}
+
+impl FromStr for RegistryRecord {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns = s.split_ascii_whitespace().collect::<Vec<_>>();
+
+        let (free_date, delegated) = match columns.len() {
+            5 => (None, columns[4]),
+            6 => (Some(parse_date(columns[4])?), columns[5]),
+            count => return Err(RecordError::Columns(count)),
+        };
+
+        Ok(RegistryRecord {
+            domain: columns[0].parse().map_err(RecordError::Domain)?,
+            registrar: columns[1].into(),
+            created: parse_date(columns[2])?,
+            paid_till: parse_date(columns[3])?,
+            free_date,
+            delegated: match delegated {
+                "1" => true,
+                "0" => false,
+                _ => return Err(RecordError::Delegated(delegated.into())),
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    Malformed {
+        line: usize,
+        text: String,
+        error: RecordError,
+    },
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(err) => write!(f, "can't read registry: {}", err),
+            RegistryError::Malformed { line, text, error } => {
+                write!(f, "line {}: {} in '{}'", line, error, text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Потоковый разбор файла реестра. Пустые строки пропускаются, на каждую некорректную строку
+/// возвращается ошибка с ее номером, после чего разбор продолжается.
+pub struct RegistryReader<L> {
+    lines: L,
+    line: usize,
+}
+
+impl<R: BufRead> RegistryReader<io::Lines<R>> {
+    pub fn new(reader: R) -> Self {
+        RegistryReader::from_lines(reader.lines())
+    }
+}
+
+impl<L> RegistryReader<L>
+where
+    L: Iterator<Item = io::Result<String>>,
+{
+    /// Разбирает уже прочитанные строки, например, отсортированные [`crate::ExternalSort`].
+    pub fn from_lines(lines: L) -> Self {
+        RegistryReader { lines, line: 0 }
+    }
+}
+
+impl<L> Iterator for RegistryReader<L>
+where
+    L: Iterator<Item = io::Result<String>>,
+{
+    type Item = Result<RegistryRecord, RegistryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.lines.next()? {
+                Ok(text) => text,
+                Err(err) => return Some(Err(RegistryError::Io(err))),
+            };
+            self.line += 1;
+
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            return Some(text.parse().map_err(|error| RegistryError::Malformed {
+                line: self.line,
+                text,
+                error,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordError, RegistryError, RegistryReader, RegistryRecord};
+    use crate::domain::DomainError;
+    use chrono::NaiveDate;
+
+    const REGISTRY: &str = include_str!("../fixtures/registry.txt");
+    const MALFORMED: &str = include_str!("../fixtures/registry_malformed.txt");
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parse_record() {
+        let record: RegistryRecord = "EXAMPLE.RU\tR01-RU\t30.12.1999\t30.12.2026\t30.01.2027\t1"
+            .parse()
+            .unwrap();
+
+        assert_eq!("example.ru", record.domain.as_str());
+        assert_eq!("R01-RU", record.registrar);
+        assert_eq!(date(1999, 12, 30), record.created);
+        assert_eq!(date(2026, 12, 30), record.paid_till);
+        assert_eq!(Some(date(2027, 1, 30)), record.free_date);
+        assert!(record.delegated);
+    }
+
+    #[test]
+    fn parse_record_without_free_date() {
+        let record: RegistryRecord = "EXAMPLE.RU R01-RU 30.12.1999 30.12.2026 0".parse().unwrap();
+
+        assert_eq!(None, record.free_date);
+        assert!(!record.delegated);
+    }
+
+    #[test]
+    fn parse_record_with_idn() {
+        let record: RegistryRecord = "ПРИМЕР.РФ REGRU-RF 01.01.2011 01.01.2027 1"
+            .parse()
+            .unwrap();
+
+        assert_eq!("xn--e1afmkfd.xn--p1ai", record.domain.as_str());
+        assert_eq!(
+            Err(RecordError::Domain(DomainError::Character(
+                "BAD_NAME.RU".into(),
+                '_'
+            ))),
+            "BAD_NAME.RU REGRU-RU 01.01.2011 01.01.2027 1".parse::<RegistryRecord>()
+        );
+    }
+
+    #[test]
+    fn read_registry() {
+        let records = RegistryReader::new(REGISTRY.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(5, records.len());
+        assert_eq!("0-0.ru", records[0].domain.as_str());
+        assert_eq!("RU-CENTER-RU", records[1].registrar);
+        assert_eq!(date(2026, 9, 18), records[2].paid_till);
+        assert_eq!("yandex.ru", records[4].domain.as_str());
+    }
+
+    #[test]
+    fn read_malformed_registry() {
+        let results = RegistryReader::new(MALFORMED.as_bytes()).collect::<Vec<_>>();
+
+        assert_eq!(5, results.len());
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(RegistryError::Malformed {
+                line: 2,
+                error: RecordError::Columns(3),
+                ..
+            })
+        ));
+        assert!(matches!(
+            results[2],
+            Err(RegistryError::Malformed {
+                line: 3,
+                error: RecordError::Date(ref value),
+                ..
+            }) if value == "31.02.2009"
+        ));
+        assert!(matches!(
+            results[3],
+            Err(RegistryError::Malformed {
+                line: 5,
+                error: RecordError::Delegated(ref value),
+                ..
+            }) if value == "yes"
+        ));
+        assert_eq!("yandex.ru", results[4].as_ref().unwrap().domain.as_str());
+    }
+}
@@ -0,0 +1,277 @@
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec;
+
+const MIB: usize = 1024 * 1024;
+/// Сколько памяти, кроме самой строки, занимает строка в буфере сортировки.
+const LINE_OVERHEAD: usize = mem::size_of::<String>();
+
+static SORT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Сортировка слиянием во внешней памяти для списков, не помещающихся в память.
+///
+/// Строки накапливаются в буфере размером не больше `memory` мегабайт, отсортированный буфер
+/// записывается во временный файл, после чего файлы сливаются. Повторяющиеся строки
+/// удаляются.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalSort {
+    #[serde(default = "ExternalSort::default_memory")]
+    pub memory: usize,
+    /// Каталог для временных файлов, по умолчанию системный.
+    pub dir: Option<PathBuf>,
+}
+
+impl ExternalSort {
+    fn default_memory() -> usize {
+        256
+    }
+
+    /// Сортирует строки, ограничивая расход памяти `memory` мегабайтами.
+    pub fn sort<I>(&self, lines: I) -> io::Result<Sorted>
+    where
+        I: IntoIterator<Item = io::Result<String>>,
+    {
+        self.sort_with_budget(lines, self.memory.max(1) * MIB)
+    }
+
+    fn sort_with_budget<I>(&self, lines: I, budget: usize) -> io::Result<Sorted>
+    where
+        I: IntoIterator<Item = io::Result<String>>,
+    {
+        let mut chunk = vec![];
+        let mut size = 0;
+        let mut runs = Runs::new(self.dir.clone().unwrap_or_else(std::env::temp_dir));
+
+        for line in lines {
+            let line = line?;
+            size += line.len() + LINE_OVERHEAD;
+            chunk.push(line);
+
+            if size >= budget {
+                runs.write(&mut chunk)?;
+                size = 0;
+            }
+        }
+
+        if runs.files.is_empty() {
+            chunk.sort_unstable();
+            chunk.dedup();
+
+            return Ok(Sorted {
+                source: Source::Memory(chunk.into_iter()),
+                last: None,
+            });
+        }
+        if !chunk.is_empty() {
+            runs.write(&mut chunk)?;
+        }
+
+        runs.merge()
+    }
+}
+
+impl Default for ExternalSort {
+    fn default() -> Self {
+        ExternalSort {
+            memory: ExternalSort::default_memory(),
+            dir: None,
+        }
+    }
+}
+
+/// Проверяет, что строки идут по неубыванию.
+pub fn is_sorted<I>(lines: I) -> io::Result<bool>
+where
+    I: IntoIterator<Item = io::Result<String>>,
+{
+    let mut last: Option<String> = None;
+
+    for line in lines {
+        let line = line?;
+
+        if last.as_ref().map_or(false, |last| *last > line) {
+            return Ok(false);
+        }
+        last = Some(line);
+    }
+
+    Ok(true)
+}
+
+/// Отсортированные участки, записанные во временный каталог.
+struct Runs {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl Runs {
+    fn new(parent: PathBuf) -> Self {
+        let id = SORT_ID.fetch_add(1, Ordering::Relaxed);
+
+        Runs {
+            dir: parent.join(format!("zones-sort-{}-{}", std::process::id(), id)),
+            files: vec![],
+        }
+    }
+
+    fn write(&mut self, chunk: &mut Vec<String>) -> io::Result<()> {
+        chunk.sort_unstable();
+        chunk.dedup();
+
+        if self.files.is_empty() {
+            fs::create_dir_all(&self.dir)?;
+        }
+        let path = self.dir.join(format!("run-{}", self.files.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for line in chunk.drain(..) {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+
+        self.files.push(path);
+        Ok(())
+    }
+
+    fn merge(self) -> io::Result<Sorted> {
+        let mut readers = vec![];
+        let mut heap = BinaryHeap::new();
+
+        for (index, path) in self.files.iter().enumerate() {
+            let mut lines = BufReader::new(File::open(path)?).lines();
+
+            if let Some(line) = lines.next() {
+                heap.push(Reverse((line?, index)));
+            }
+            readers.push(lines);
+        }
+
+        Ok(Sorted {
+            source: Source::Merge {
+                heap,
+                readers,
+                _runs: self,
+            },
+            last: None,
+        })
+    }
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        if !self.files.is_empty() {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+enum Source {
+    Memory(vec::IntoIter<String>),
+    Merge {
+        heap: BinaryHeap<Reverse<(String, usize)>>,
+        readers: Vec<Lines<BufReader<File>>>,
+        // Временные файлы удаляются, когда итератор уничтожается.
+        _runs: Runs,
+    },
+}
+
+/// Отсортированные строки без повторов. Временные файлы удаляются вместе с итератором.
+pub struct Sorted {
+    source: Source,
+    last: Option<String>,
+}
+
+impl Sorted {
+    fn next_line(&mut self) -> Option<io::Result<String>> {
+        match self.source {
+            Source::Memory(ref mut lines) => lines.next().map(Ok),
+            Source::Merge {
+                ref mut heap,
+                ref mut readers,
+                ..
+            } => {
+                let Reverse((line, index)) = heap.pop()?;
+
+                match readers[index].next() {
+                    Some(Ok(next)) => heap.push(Reverse((next, index))),
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => {}
+                }
+
+                Some(Ok(line))
+            }
+        }
+    }
+}
+
+impl Iterator for Sorted {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.next_line()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+
+            if self.last.as_ref() != Some(&line) {
+                self.last = Some(line.clone());
+                return Some(Ok(line));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_sorted, ExternalSort};
+    use std::io;
+
+    fn lines(items: &[&str]) -> Vec<io::Result<String>> {
+        items.iter().map(|x| Ok(x.to_string())).collect()
+    }
+
+    fn sort(items: Vec<io::Result<String>>, budget: usize) -> Vec<String> {
+        let sort = ExternalSort::default();
+
+        sort.sort_with_budget(items, budget)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn sort_in_memory() {
+        let sorted = sort(lines(&["c", "a", "b", "a"]), 1024);
+
+        assert_eq!(vec!["a", "b", "c"], sorted);
+    }
+
+    #[test]
+    fn sort_on_disk() {
+        let items = (0..1000)
+            .rev()
+            .map(|x| Ok(format!("{:04}.RU", x % 700)))
+            .collect::<Vec<_>>();
+
+        // Буфер вмещает около двадцати строк, поэтому получается несколько десятков файлов.
+        let sorted = sort(items, 640);
+
+        assert_eq!(700, sorted.len());
+        assert_eq!("0000.RU", sorted[0]);
+        assert_eq!("0699.RU", sorted[699]);
+        assert!(sorted.windows(2).all(|x| x[0] < x[1]));
+    }
+
+    #[test]
+    fn check_order() {
+        assert!(is_sorted(lines(&["A.RU 1", "A.RU 1", "B.RU 0"])).unwrap());
+        assert!(!is_sorted(lines(&["B.RU 0", "A.RU 1"])).unwrap());
+        assert!(is_sorted(lines(&[])).unwrap());
+    }
+}
@@ -1,4 +1,128 @@
 use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::iter::Peekable;
+
+/// Элемент разницы двух отсортированных последовательностей.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diff<L, R> {
+    /// Элемент есть только в левой последовательности (`A\B`).
+    Left(L),
+    /// Элемент есть только в правой последовательности (`B\A`).
+    Right(R),
+    /// Элемент есть в обеих последовательностях.
+    Both(L, R),
+}
+
+/// Ленивое слияние двух отсортированных последовательностей.
+///
+/// Элементы сравниваются функцией `cmp` и возвращаются по одному, поэтому память не зависит
+/// от длины последовательностей. Совпадающие элементы по умолчанию пропускаются,
+/// [`DiffIter::with_both`] включает их в выдачу.
+pub struct DiffIter<A: Iterator, B: Iterator, F> {
+    a: Peekable<A>,
+    b: Peekable<B>,
+    cmp: F,
+    both: bool,
+}
+
+/// Функция сравнения элементов в [`diff`].
+pub type Compare<E> = fn(&E, &E) -> Ordering;
+
+/// Разница двух отсортированных по возрастанию последовательностей одного типа.
+pub fn diff<A, B, E>(a: A, b: B) -> DiffIter<A::IntoIter, B::IntoIter, Compare<E>>
+where
+    A: IntoIterator<Item = E>,
+    B: IntoIterator<Item = E>,
+    E: Ord,
+{
+    diff_by(a, b, E::cmp)
+}
+
+/// Разница двух последовательностей, отсортированных в порядке, который задает `cmp`.
+pub fn diff_by<A, B, F>(a: A, b: B, cmp: F) -> DiffIter<A::IntoIter, B::IntoIter, F>
+where
+    A: IntoIterator,
+    B: IntoIterator,
+    F: FnMut(&A::Item, &B::Item) -> Ordering,
+{
+    DiffIter {
+        a: a.into_iter().peekable(),
+        b: b.into_iter().peekable(),
+        cmp,
+        both: false,
+    }
+}
+
+impl<A: Iterator, B: Iterator, F> DiffIter<A, B, F> {
+    /// Возвращать совпадающие элементы как [`Diff::Both`].
+    pub fn with_both(mut self) -> Self {
+        self.both = true;
+        self
+    }
+}
+
+impl<A, B, F> Iterator for DiffIter<A, B, F>
+where
+    A: Iterator,
+    B: Iterator,
+    F: FnMut(&A::Item, &B::Item) -> Ordering,
+{
+    type Item = Diff<A::Item, B::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.a.peek(), self.b.peek()) {
+                (Some(x), Some(y)) => (self.cmp)(x, y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => return None,
+            };
+
+            match order {
+                Ordering::Less => return self.a.next().map(Diff::Left),
+                Ordering::Greater => return self.b.next().map(Diff::Right),
+                Ordering::Equal => {
+                    let x = self.a.next().unwrap();
+                    let y = self.b.next().unwrap();
+
+                    if self.both {
+                        return Some(Diff::Both(x, y));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Изменение элемента при переходе от старой последовательности к новой.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    Added(T),
+    Removed(T),
+    Modified { old: T, new: T },
+}
+
+/// Разница двух отсортированных по ключу последовательностей: старой `old` и новой `new`.
+///
+/// Элементы с одинаковым ключом сравниваются целиком: если они различаются, возвращается
+/// [`Change::Modified`] со старым и новым значениями, неизменившиеся элементы пропускаются.
+pub fn keyed_diff<A, B, T, K, F>(old: A, new: B, key: F) -> impl Iterator<Item = Change<T>>
+where
+    A: IntoIterator<Item = T>,
+    B: IntoIterator<Item = T>,
+    T: PartialEq,
+    K: Ord + ?Sized,
+    F: Fn(&T) -> &K,
+{
+    diff_by(old, new, move |x, y| key(x).cmp(key(y)))
+        .with_both()
+        .filter_map(|item| match item {
+            Diff::Left(old) => Some(Change::Removed(old)),
+            Diff::Right(new) => Some(Change::Added(new)),
+            Diff::Both(old, new) if old == new => None,
+            Diff::Both(old, new) => Some(Change::Modified { old, new }),
+        })
+}
 
 /// Алгоритм симметрической разницы `A∆B` двух массивов.
 ///
@@ -10,41 +134,179 @@ where
     B: IntoIterator<Item = E>,
     E: Ord,
 {
-    let mut a = a.into_iter();
-    let mut b = b.into_iter();
-    let mut a_value = a.next();
-    let mut b_value = b.next();
     let mut first = vec![];
     let mut second = vec![];
 
-    while a_value.is_some() && b_value.is_some() {
-        match a_value.as_ref().unwrap().cmp(&b_value.as_ref().unwrap()) {
+    for item in diff(a, b) {
+        match item {
+            Diff::Left(x) => first.push(x),
+            Diff::Right(y) => second.push(y),
+            Diff::Both(..) => {}
+        }
+    }
+
+    (first, second)
+}
+
+/// Входная последовательность сравнения: левая (`A`) или правая (`B`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// Нарушение порядка сортировки: элемент с номером `index` (с нуля) во входной
+/// последовательности `side` меньше предшествующего ему элемента.
+#[derive(Debug, PartialEq)]
+pub struct OrderError<K> {
+    pub side: Side,
+    pub index: usize,
+    pub previous: K,
+    pub current: K,
+}
+
+impl<K: Debug> Display for OrderError<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} input is not sorted: item {} ({:?}) goes after {:?}",
+            self.side, self.index, self.current, self.previous
+        )
+    }
+}
+
+impl<K: Debug> std::error::Error for OrderError<K> {}
+
+/// Итератор, проверяющий, что ключи элементов идут по возрастанию.
+///
+/// Элементы с тем же ключом, что и у предыдущего, пропускаются (остается первый из них),
+/// их число возвращает [`Ascending::duplicates`]. Первый элемент, нарушающий порядок,
+/// возвращается как ошибка [`OrderError`].
+pub struct Ascending<I, F, K> {
+    iter: I,
+    key: F,
+    side: Side,
+    index: usize,
+    last: Option<K>,
+    duplicates: usize,
+}
+
+/// Оборачивает последовательность в проверку порядка по ключу `key`.
+pub fn ascending<I, F, K>(iter: I, side: Side, key: F) -> Ascending<I::IntoIter, F, K>
+where
+    I: IntoIterator,
+    F: FnMut(&I::Item) -> K,
+    K: Ord,
+{
+    Ascending {
+        iter: iter.into_iter(),
+        key,
+        side,
+        index: 0,
+        last: None,
+        duplicates: 0,
+    }
+}
+
+impl<I, F, K> Ascending<I, F, K> {
+    /// Число пропущенных элементов-дубликатов.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
+
+impl<I, F, K> Iterator for Ascending<I, F, K>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+    K: Ord,
+{
+    type Item = Result<I::Item, OrderError<K>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let value = self.iter.next()?;
+            let key = (self.key)(&value);
+            let index = self.index;
+            self.index += 1;
+
+            match self.last.as_ref().map(|last| key.cmp(last)) {
+                Some(Ordering::Equal) => self.duplicates += 1,
+                Some(Ordering::Less) => {
+                    return Some(Err(OrderError {
+                        side: self.side,
+                        index,
+                        previous: self.last.take().unwrap(),
+                        current: key,
+                    }));
+                }
+                _ => {
+                    self.last = Some(key);
+                    return Some(Ok(value));
+                }
+            }
+        }
+    }
+}
+
+/// Симметрическая разница `A∆B` с проверкой входных последовательностей.
+///
+/// В отличие от [`symmetric_diff`] возвращает ошибку, если одна из последовательностей
+/// не отсортирована по возрастанию, а повторяющиеся элементы учитывает один раз.
+pub fn symmetric_diff_checked<A, B, E>(a: A, b: B) -> Result<(Vec<E>, Vec<E>), OrderError<E>>
+where
+    A: IntoIterator<Item = E>,
+    B: IntoIterator<Item = E>,
+    E: Ord + Clone,
+{
+    let mut a = ascending(a, Side::Left, E::clone);
+    let mut b = ascending(b, Side::Right, E::clone);
+    let mut a_value = a.next().transpose()?;
+    let mut b_value = b.next().transpose()?;
+    let mut first = vec![];
+    let mut second = vec![];
+
+    while let (Some(x), Some(y)) = (&a_value, &b_value) {
+        match x.cmp(y) {
             Ordering::Less => {
-                first.push(a_value.unwrap());
-                a_value = a.next();
+                first.push(a_value.take().unwrap());
+                a_value = a.next().transpose()?;
             }
             Ordering::Greater => {
-                second.push(b_value.unwrap());
-                b_value = b.next();
+                second.push(b_value.take().unwrap());
+                b_value = b.next().transpose()?;
             }
             Ordering::Equal => {
-                a_value = a.next();
-                b_value = b.next();
+                a_value = a.next().transpose()?;
+                b_value = b.next().transpose()?;
             }
         }
     }
 
     if let Some(value) = a_value {
         first.push(value);
-        first.extend(a);
+        for value in a {
+            first.push(value?);
+        }
     }
 
     if let Some(value) = b_value {
         second.push(value);
-        second.extend(b);
+        for value in b {
+            second.push(value?);
+        }
     }
 
-    (first, second)
+    Ok((first, second))
 }
 
 #[cfg(test)]
@@ -124,4 +386,150 @@ mod tests {
             assert_eq!("google.com", second[0]);
         }
     }
+
+    mod symmetric_diff_checked {
+        use crate::tools::{symmetric_diff_checked, OrderError, Side};
+
+        #[test]
+        fn with_sorted_arrays() {
+            let a = vec![0, 1, 2];
+            let b = vec![2, 3, 4];
+
+            let (first, second) = symmetric_diff_checked(a, b).unwrap();
+            assert_eq!(vec![0, 1], first);
+            assert_eq!(vec![3, 4], second);
+        }
+
+        #[test]
+        fn with_duplicates() {
+            let a = vec![0, 0, 1, 2, 2];
+            let b = vec![2, 3, 3];
+
+            let (first, second) = symmetric_diff_checked(a, b).unwrap();
+            assert_eq!(vec![0, 1], first);
+            assert_eq!(vec![3], second);
+        }
+
+        #[test]
+        fn with_unsorted_first_array() {
+            let a = vec![0, 2, 1];
+            let b = vec![0, 1, 2];
+
+            let result = symmetric_diff_checked(a, b);
+            assert_eq!(
+                Err(OrderError {
+                    side: Side::Left,
+                    index: 2,
+                    previous: 2,
+                    current: 1,
+                }),
+                result
+            );
+        }
+
+        #[test]
+        fn with_unsorted_tail_of_second_array() {
+            let a = vec!["a".to_string()];
+            let b = vec!["b".to_string(), "d".to_string(), "c".to_string()];
+
+            let err = symmetric_diff_checked(a, b).unwrap_err();
+            assert_eq!(Side::Right, err.side);
+            assert_eq!(
+                "right input is not sorted: item 2 (\"c\") goes after \"d\"",
+                err.to_string()
+            );
+        }
+    }
+
+    mod diff {
+        use crate::tools::{diff, diff_by, Diff};
+
+        #[test]
+        fn without_common_items() {
+            let items = diff(vec![0, 1, 3], vec![1, 2, 3, 4]).collect::<Vec<_>>();
+
+            assert_eq!(vec![Diff::Left(0), Diff::Right(2), Diff::Right(4)], items);
+        }
+
+        #[test]
+        fn with_common_items() {
+            let items = diff(vec![0, 1], vec![1, 2]).with_both().collect::<Vec<_>>();
+
+            assert_eq!(vec![Diff::Left(0), Diff::Both(1, 1), Diff::Right(2)], items);
+        }
+
+        #[test]
+        fn with_different_types() {
+            let a = vec![("a", 1), ("b", 2)];
+            let b = vec!["b".to_string(), "c".to_string()];
+
+            let items = diff_by(a, b, |x, y| x.0.cmp(y.as_str()))
+                .with_both()
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                vec![
+                    Diff::Left(("a", 1)),
+                    Diff::Both(("b", 2), "b".to_string()),
+                    Diff::Right("c".to_string()),
+                ],
+                items
+            );
+        }
+
+        #[test]
+        fn is_lazy() {
+            let mut items = diff(0.., vec![0, 2]);
+
+            assert_eq!(Some(Diff::Left(1)), items.next());
+            assert_eq!(Some(Diff::Left(3)), items.next());
+        }
+    }
+
+    mod keyed_diff {
+        use crate::tools::{keyed_diff, Change};
+
+        #[test]
+        fn with_changed_values() {
+            let old = vec![("a", 1), ("b", 2), ("c", 3)];
+            let new = vec![("b", 2), ("c", 4), ("d", 5)];
+
+            let changes = keyed_diff(old, new, |x| x.0).collect::<Vec<_>>();
+
+            assert_eq!(
+                vec![
+                    Change::Removed(("a", 1)),
+                    Change::Modified {
+                        old: ("c", 3),
+                        new: ("c", 4),
+                    },
+                    Change::Added(("d", 5)),
+                ],
+                changes
+            );
+        }
+
+        #[test]
+        fn with_equal_arrays() {
+            let old = vec![("a", 1), ("b", 2)];
+
+            assert_eq!(0, keyed_diff(old.clone(), old, |x| x.0).count());
+        }
+
+        #[test]
+        fn with_borrowed_keys() {
+            let old = vec![("example.ru".to_string(), true)];
+            let new = vec![("example.ru".to_string(), false)];
+
+            let changes = keyed_diff(old, new, |x| x.0.as_str()).collect::<Vec<_>>();
+
+            assert!(matches!(
+                changes[0],
+                Change::Modified {
+                    old: (_, true),
+                    new: (_, false)
+                }
+            ));
+        }
+    }
 }